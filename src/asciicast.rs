@@ -0,0 +1,411 @@
+//! Reading and writing asciinema's asciicast v2 format.
+//!
+//! An asciicast file is a header line containing a JSON object describing
+//! the recording, followed by one JSON array per line for each event, of
+//! the form `[time, type, data]`.
+
+use tokio::io::AsyncReadExt as _;
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<std::collections::BTreeMap<String, String>>,
+}
+
+impl Header {
+    pub fn new((rows, cols): (u16, u16)) -> Self {
+        Self {
+            version: 2,
+            width: cols,
+            height: rows,
+            ..Self::default()
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.height, self.width)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Output(std::time::Duration, String),
+    Input(std::time::Duration, String),
+    Marker(std::time::Duration, String),
+    Resize(std::time::Duration, (u16, u16)),
+}
+
+impl Event {
+    pub fn time(&self) -> std::time::Duration {
+        match self {
+            Self::Output(time, _)
+            | Self::Input(time, _)
+            | Self::Marker(time, _)
+            | Self::Resize(time, _) => *time,
+        }
+    }
+
+    fn parse(line: &[u8]) -> anyhow::Result<Option<Self>> {
+        let (time, ty, data): (f64, String, String) =
+            serde_json::from_slice(line)?;
+        let time =
+            std::time::Duration::try_from_secs_f64(time).unwrap_or_default();
+        Ok(Some(match ty.as_str() {
+            "o" => Self::Output(time, data),
+            "i" => Self::Input(time, data),
+            "m" => Self::Marker(time, data),
+            "r" => {
+                let Some((cols, rows)) = data.split_once('x') else {
+                    anyhow::bail!("invalid asciicast resize event: {data}");
+                };
+                Self::Resize(time, (rows.parse()?, cols.parse()?))
+            }
+            // the format allows for new event types to be added, which
+            // should be ignored by readers which don't understand them
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// Returns whether `data` looks like the start of an asciicast file, rather
+/// than a ttyrec file.
+pub fn detect(data: &[u8]) -> bool {
+    // a ttyrec file starts with a binary timestamp, which is vanishingly
+    // unlikely to look like the start of a JSON object
+    data.starts_with(b"{\"")
+}
+
+/// Reads asciicast events from a [`tokio::io::AsyncRead`] instance.
+pub struct Reader<T> {
+    input: T,
+    buf: Vec<u8>,
+    read: usize,
+}
+
+impl<T: tokio::io::AsyncRead + std::marker::Unpin + Send> Reader<T> {
+    pub fn new(input: T) -> Self {
+        Self {
+            input,
+            buf: vec![],
+            read: 0,
+        }
+    }
+
+    /// Reads the header line. Returns `None` if the end of the input was
+    /// reached before a full line could be read, in which case this can be
+    /// called again if more data becomes available.
+    pub async fn read_header(&mut self) -> anyhow::Result<Option<Header>> {
+        let Some(line) = self.read_line().await? else {
+            return Ok(None);
+        };
+        let header: Header = serde_json::from_slice(&line)?;
+        if header.version != 2 {
+            anyhow::bail!("unsupported asciicast version {}", header.version);
+        }
+        Ok(Some(header))
+    }
+
+    /// Reads the next event. Returns `None` if the end of the input was
+    /// reached before a full line could be read, in which case this can be
+    /// called again if more data becomes available.
+    pub async fn read_event(&mut self) -> anyhow::Result<Option<Event>> {
+        loop {
+            let Some(line) = self.read_line().await? else {
+                return Ok(None);
+            };
+            if let Some(event) = Event::parse(&line)? {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(end) =
+                self.buf[self.read..].iter().position(|&c| c == b'\n')
+            {
+                let end = self.read + end;
+                let line = self.buf[..end].to_vec();
+                self.buf.drain(..=end);
+                self.read = 0;
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return Ok(Some(line));
+            }
+            self.read = self.buf.len();
+
+            let mut chunk = [0; 4096];
+            let bytes = self.input.read(&mut chunk).await?;
+            if bytes == 0 {
+                return Ok(None);
+            }
+            self.buf.extend(&chunk[..bytes]);
+        }
+    }
+}
+
+/// Converts recorded output into asciicast event lines.
+pub struct Encoder {
+    size: (u16, u16),
+    // output can be split in the middle of a UTF-8 character, but asciicast
+    // output events must be valid strings
+    partial: Vec<u8>,
+}
+
+impl Encoder {
+    /// Returns a new encoder, along with the header line to write before
+    /// any events.
+    pub fn new(header: &Header) -> (Self, Vec<u8>) {
+        // serializing a struct with only string keys can't fail
+        let mut line = serde_json::to_vec(header).unwrap();
+        line.push(b'\n');
+        (
+            Self {
+                size: header.size(),
+                partial: vec![],
+            },
+            line,
+        )
+    }
+
+    /// Returns the event lines for a frame of output recorded at `time`
+    /// since the start of the recording. Resize sequences (as written by
+    /// [`crate::resize::sequence`]) are converted into resize events.
+    pub fn frame(
+        &mut self,
+        time: std::time::Duration,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut lines = vec![];
+        for chunk in crate::resize::chunks(data) {
+            match chunk {
+                crate::resize::Chunk::Data(data) => {
+                    self.partial.extend(data);
+                    let output = self.take_output();
+                    if !output.is_empty() {
+                        write_event(&mut lines, time, "o", &output);
+                    }
+                }
+                crate::resize::Chunk::Resize(size) => {
+                    if size != self.size {
+                        self.size = size;
+                        write_event(
+                            &mut lines,
+                            time,
+                            "r",
+                            &format!("{}x{}", size.1, size.0),
+                        );
+                    }
+                }
+            }
+        }
+        lines
+    }
+
+    /// Returns the event line for an event which isn't part of the
+    /// terminal output (input or a marker). Output and resize events are
+    /// handled the same way as [`Self::frame`].
+    pub fn event(&mut self, event: &Event) -> Vec<u8> {
+        match event {
+            Event::Output(time, data) => self.frame(*time, data.as_bytes()),
+            Event::Resize(time, size) => {
+                self.frame(*time, &crate::resize::sequence(*size))
+            }
+            Event::Input(time, data) => {
+                let mut lines = vec![];
+                write_event(&mut lines, *time, "i", data);
+                lines
+            }
+            Event::Marker(time, data) => {
+                let mut lines = vec![];
+                write_event(&mut lines, *time, "m", data);
+                lines
+            }
+        }
+    }
+
+    fn take_output(&mut self) -> String {
+        let mut output = String::new();
+        let mut rest = &self.partial[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    output.push_str(s);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    // from_utf8 just said that this part is valid
+                    output.push_str(std::str::from_utf8(valid).unwrap());
+                    if let Some(len) = e.error_len() {
+                        output.push(char::REPLACEMENT_CHARACTER);
+                        rest = &invalid[len..];
+                    } else {
+                        // an incomplete character at the end, which will
+                        // hopefully be completed by the next frame
+                        rest = invalid;
+                        break;
+                    }
+                }
+            }
+        }
+        self.partial = rest.to_vec();
+        output
+    }
+}
+
+fn write_event(
+    lines: &mut Vec<u8>,
+    time: std::time::Duration,
+    ty: &str,
+    data: &str,
+) {
+    // ttyrec timestamps only have microsecond precision, and printing more
+    // digits than that would just be noise
+    let time =
+        std::time::Duration::new(time.as_secs(), time.subsec_micros() * 1000);
+    // serializing a tuple of a float and strings can't fail
+    serde_json::to_writer(&mut *lines, &(time.as_secs_f64(), ty, data))
+        .unwrap();
+    lines.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    fn millis(millis: u64) -> std::time::Duration {
+        std::time::Duration::from_millis(millis)
+    }
+
+    async fn read_all(data: &[u8]) -> (super::Header, Vec<super::Event>) {
+        let mut reader = super::Reader::new(data);
+        let header = reader.read_header().await.unwrap().unwrap();
+        let mut events = vec![];
+        while let Some(event) = reader.read_event().await.unwrap() {
+            events.push(event);
+        }
+        (header, events)
+    }
+
+    #[test]
+    fn detect() {
+        assert!(super::detect(b"{\"version\": 2}"));
+        assert!(!super::detect(b"\x01\x02\x03\x04"));
+        assert!(!super::detect(b""));
+    }
+
+    #[tokio::test]
+    async fn read() {
+        let data = b"{\"version\": 2, \"width\": 80, \"height\": 24, \
+            \"timestamp\": 1704164645, \"command\": \"bash\"}\n\
+            [0.5, \"o\", \"hello\"]\n\
+            \n\
+            [0.75, \"i\", \"ls\\r\"]\n\
+            [1.0, \"x\", \"from a newer version\"]\n\
+            [1.25, \"m\", \"marker\"]\n\
+            [1.5, \"r\", \"120x40\"]\n\
+            [2.0, \"o\", \"caf\\u00e9\"]\n\
+            [2.5, \"o\", \"cut off";
+        let (header, events) = read_all(data).await;
+        assert_eq!(header.size(), (24, 80));
+        assert_eq!(header.timestamp, Some(1_704_164_645));
+        assert_eq!(header.command.as_deref(), Some("bash"));
+        assert_eq!(
+            events,
+            vec![
+                super::Event::Output(millis(500), "hello".to_string()),
+                super::Event::Input(millis(750), "ls\r".to_string()),
+                super::Event::Marker(millis(1250), "marker".to_string()),
+                super::Event::Resize(millis(1500), (40, 120)),
+                super::Event::Output(millis(2000), "caf\u{e9}".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn read_errors() {
+        let mut reader = super::Reader::new(
+            &b"{\"version\": 1, \"width\": 80, \"height\": 24}\n"[..],
+        );
+        assert!(reader.read_header().await.is_err());
+        let mut reader = super::Reader::new(&b"[1.0, \"r\", \"80\"]\n"[..]);
+        assert!(reader.read_event().await.is_err());
+    }
+
+    #[test]
+    fn encode_split_utf8() {
+        let (mut encoder, _) =
+            super::Encoder::new(&super::Header::new((24, 80)));
+        // the first frame ends partway through a character, which is
+        // written with the frame which completes it
+        assert_eq!(
+            encoder.frame(millis(100), b"caf\xc3"),
+            b"[0.1,\"o\",\"caf\"]\n"
+        );
+        assert_eq!(
+            encoder.frame(millis(200), b"\xa9"),
+            b"[0.2,\"o\",\"\xc3\xa9\"]\n"
+        );
+        // a frame with only part of a character writes nothing
+        assert_eq!(encoder.frame(millis(300), b"\xe2\x82"), b"");
+        assert_eq!(
+            encoder.frame(millis(400), b"\xac!"),
+            "[0.4,\"o\",\"\u{20ac}!\"]\n".as_bytes()
+        );
+        // bytes which can never be valid are replaced
+        assert_eq!(
+            encoder.frame(millis(500), b"a\xffb"),
+            "[0.5,\"o\",\"a\u{fffd}b\"]\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn encode_resizes() {
+        let (mut encoder, header) =
+            super::Encoder::new(&super::Header::new((24, 80)));
+        assert!(header.ends_with(b"\n"));
+        // resizing to the size in the header is redundant
+        assert_eq!(
+            encoder.frame(millis(0), &crate::resize::sequence((24, 80))),
+            b""
+        );
+        let mut data = b"a".to_vec();
+        data.extend(crate::resize::sequence((40, 120)));
+        data.extend(b"b");
+        assert_eq!(
+            encoder.frame(millis(1000), &data),
+            b"[1.0,\"o\",\"a\"]\n[1.0,\"r\",\"120x40\"]\n[1.0,\"o\",\"b\"]\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let mut header = super::Header::new((24, 80));
+        header.command = Some("bash".to_string());
+        let events = vec![
+            super::Event::Output(millis(250), "hello\r\n".to_string()),
+            super::Event::Input(millis(500), "\u{1b}[A".to_string()),
+            super::Event::Marker(millis(750), "here".to_string()),
+            super::Event::Resize(millis(1000), (40, 120)),
+            super::Event::Output(millis(1_234_567), "\"quoted\"".to_string()),
+        ];
+        let (mut encoder, mut data) = super::Encoder::new(&header);
+        for event in &events {
+            data.extend(encoder.event(event));
+        }
+        let (read_header, read_events) = read_all(&data).await;
+        assert_eq!(read_header.size(), (24, 80));
+        assert_eq!(read_header.command.as_deref(), Some("bash"));
+        assert_eq!(read_events, events);
+    }
+}
@@ -0,0 +1,344 @@
+// matches the end of a typical shell prompt, for recordings made with a
+// shell which doesn't emit any OSC 133 markers or title changes
+const PROMPT: &str = r"[$#%>\x{276f}] $";
+
+/// A command run during the recording (or for recordings detected by title
+/// changes, a span of time with the same terminal title).
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub name: String,
+    /// The first frame of the command.
+    pub start: usize,
+    /// The time of the first frame, relative to the first frame of the
+    /// recording.
+    pub time: std::time::Duration,
+    /// The frame where the command finished, if it did.
+    pub end: Option<usize>,
+    pub exit: Option<i32>,
+}
+
+#[derive(Debug)]
+pub enum Marker {
+    /// `OSC 133 ; A`, which starts a prompt.
+    Prompt,
+    /// `OSC 133 ; C`, which starts a command, along with the command line.
+    Start(String),
+    /// `OSC 133 ; D`, which finishes a command, along with the exit code.
+    End(Option<i32>),
+    Title(String),
+}
+
+/// Parser callbacks which note shell integration sequences as they are
+/// parsed, since the screen at that exact point is needed to find the
+/// command line.
+#[derive(Debug, Default)]
+pub struct Markers {
+    markers: Vec<Marker>,
+    // where the prompt ended, as marked by OSC 133 ; B
+    input_start: Option<(u16, u16)>,
+}
+
+impl Markers {
+    /// Returns the markers found since the last call.
+    pub fn take(&mut self) -> Vec<Marker> {
+        std::mem::take(&mut self.markers)
+    }
+}
+
+impl vt100::Callbacks for Markers {
+    fn set_window_title(&mut self, _: &mut vt100::Screen, title: &[u8]) {
+        self.markers
+            .push(Marker::Title(String::from_utf8_lossy(title).into_owned()));
+    }
+
+    fn unhandled_osc(
+        &mut self,
+        screen: &mut vt100::Screen,
+        params: &[&[u8]],
+    ) {
+        let [b"133", kind, rest @ ..] = params else {
+            return;
+        };
+        match *kind {
+            b"A" => self.markers.push(Marker::Prompt),
+            b"B" => self.input_start = Some(screen.cursor_position()),
+            b"C" => {
+                let name = self
+                    .input_start
+                    .take()
+                    .map(|start| command_line(screen, start))
+                    .unwrap_or_default();
+                self.markers.push(Marker::Start(name));
+            }
+            b"D" => {
+                let exit = rest
+                    .first()
+                    .and_then(|code| std::str::from_utf8(code).ok())
+                    .and_then(|code| code.parse().ok());
+                self.markers.push(Marker::End(exit));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Finds the commands run during a recording, one frame at a time.
+///
+/// Commands are found from OSC 133 markers if the recording has any, and
+/// otherwise from terminal title changes, and otherwise by looking for
+/// lines which look like a shell prompt.
+pub struct Detector {
+    prompt: regex::Regex,
+    marked: Vec<Command>,
+    titled: Vec<Command>,
+    prompted: Vec<Command>,
+    // the position of the end of the prompt the cursor is currently at, and
+    // what has been typed after it so far
+    at_prompt: Option<((u16, u16), String)>,
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Self {
+            // PROMPT is a constant which is known to be valid
+            prompt: regex::Regex::new(PROMPT).unwrap(),
+            marked: vec![],
+            titled: vec![],
+            prompted: vec![],
+            at_prompt: None,
+        }
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        if !self.marked.is_empty() {
+            &self.marked
+        } else if !self.titled.is_empty() {
+            &self.titled
+        } else {
+            &self.prompted
+        }
+    }
+
+    /// Updates the list of commands with frame `idx` (recorded at `time`),
+    /// given the markers found while parsing it and the screen afterwards.
+    /// Returns whether the list of commands changed.
+    pub fn frame(
+        &mut self,
+        idx: usize,
+        time: std::time::Duration,
+        markers: Vec<Marker>,
+        screen: &vt100::Screen,
+    ) -> bool {
+        let mut changed = false;
+        for marker in markers {
+            changed = true;
+            match marker {
+                Marker::Prompt => finish(&mut self.marked, idx, None),
+                Marker::Start(name) => {
+                    finish(&mut self.marked, idx, None);
+                    self.marked.push(Command {
+                        name,
+                        start: idx,
+                        time,
+                        end: None,
+                        exit: None,
+                    });
+                }
+                Marker::End(exit) => finish(&mut self.marked, idx, exit),
+                Marker::Title(title) => {
+                    if self.titled.last().is_some_and(|c| c.name == title) {
+                        continue;
+                    }
+                    finish(&mut self.titled, idx, None);
+                    self.titled.push(Command {
+                        name: title,
+                        start: idx,
+                        time,
+                        end: None,
+                        exit: None,
+                    });
+                }
+            }
+        }
+
+        if self.marked.is_empty() && self.titled.is_empty() {
+            changed |= self.find_prompt(idx, time, screen);
+        }
+
+        changed
+    }
+
+    fn find_prompt(
+        &mut self,
+        idx: usize,
+        time: std::time::Duration,
+        screen: &vt100::Screen,
+    ) -> bool {
+        let (row, col) = screen.cursor_position();
+        let mut changed = false;
+        if let Some(((prompt_row, prompt_col), typed)) = &mut self.at_prompt {
+            if row == *prompt_row && col >= *prompt_col {
+                *typed = screen
+                    .contents_between(row, *prompt_col, row, col)
+                    .trim_end()
+                    .to_string();
+                return false;
+            }
+
+            // the cursor has left the prompt, so a command was run (unless
+            // the line was empty). if the prompt line is still above the
+            // cursor, it has the whole command line, including anything
+            // typed in the same frame as the enter key.
+            if row == *prompt_row + 1 {
+                let (_, cols) = screen.size();
+                *typed = screen
+                    .contents_between(
+                        *prompt_row,
+                        *prompt_col,
+                        *prompt_row,
+                        cols,
+                    )
+                    .trim_end()
+                    .to_string();
+            }
+            let name = typed.trim().to_string();
+            self.at_prompt = None;
+            if !name.is_empty() {
+                self.prompted.push(Command {
+                    name,
+                    start: idx,
+                    time,
+                    end: None,
+                    exit: None,
+                });
+                changed = true;
+            }
+        }
+
+        // a command which finishes quickly may print the next prompt in the
+        // same frame that it started in
+        let line = screen.contents_between(row, 0, row, col);
+        if self.prompt.is_match(&line) {
+            self.at_prompt = Some(((row, col), String::new()));
+            if self.prompted.last().is_some_and(|c| c.end.is_none()) {
+                finish(&mut self.prompted, idx, None);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+// marks the most recent command as finished at frame `idx`, if it is still
+// running
+fn finish(commands: &mut [Command], idx: usize, exit: Option<i32>) {
+    if let Some(command) = commands.last_mut() {
+        if command.end.is_none() {
+            command.end = Some(idx);
+            command.exit = exit;
+        }
+    }
+}
+
+// returns the text typed between `start` and the cursor, which is at the
+// start of the line after the command line when OSC 133 ; C is sent
+fn command_line(screen: &vt100::Screen, start: (u16, u16)) -> String {
+    let (row, col) = screen.cursor_position();
+    let (end_row, end_col) = if col == 0 && row > 0 {
+        (row - 1, screen.size().1)
+    } else {
+        (row, col)
+    };
+    // if the screen scrolled after the command line was entered, the
+    // command line will have moved up (this assumes it was a single line)
+    let start = if start.0 > end_row {
+        (end_row, start.1)
+    } else {
+        start
+    };
+    screen
+        .contents_between(start.0, start.1, end_row, end_col)
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    // feeds each of `frames` through a parser in turn, one second apart, and
+    // returns the commands found
+    fn detect(frames: &[&str]) -> Vec<super::Command> {
+        let mut parser = vt100::Parser::new_with_callbacks(
+            24,
+            80,
+            0,
+            super::Markers::default(),
+        );
+        let mut detector = super::Detector::new();
+        for (idx, frame) in frames.iter().enumerate() {
+            parser.process(frame.as_bytes());
+            let markers = parser.callbacks_mut().take();
+            detector.frame(
+                idx,
+                std::time::Duration::from_secs(idx.try_into().unwrap()),
+                markers,
+                parser.screen(),
+            );
+        }
+        detector.commands().to_vec()
+    }
+
+    #[test]
+    fn osc_133() {
+        let commands = detect(&[
+            "\x1b]133;A\x07$ \x1b]133;B\x07",
+            "ls -l",
+            "\r\n\x1b]133;C\x07total 0\r\n",
+            "\x1b]133;D;2\x07\x1b]133;A\x07$ \x1b]133;B\x07",
+            "true\r\n\x1b]133;C\x07",
+        ]);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].name, "ls -l");
+        assert_eq!(commands[0].start, 2);
+        assert_eq!(commands[0].time, std::time::Duration::from_secs(2));
+        assert_eq!(commands[0].end, Some(3));
+        assert_eq!(commands[0].exit, Some(2));
+        assert_eq!(commands[1].name, "true");
+        assert_eq!(commands[1].start, 4);
+        assert_eq!(commands[1].end, None);
+    }
+
+    #[test]
+    fn titles() {
+        let commands = detect(&[
+            "\x1b]2;vim\x07",
+            "text",
+            "\x1b]2;vim\x07",
+            "\x1b]2;shell\x07",
+        ]);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].name, "vim");
+        assert_eq!(commands[0].start, 0);
+        assert_eq!(commands[0].end, Some(3));
+        assert_eq!(commands[1].name, "shell");
+        assert_eq!(commands[1].start, 3);
+    }
+
+    #[test]
+    fn prompts() {
+        let commands = detect(&[
+            "$ ",
+            "echo",
+            " hi",
+            "\r\nhi\r\n$ ",
+            "\r\n$ ",
+            "make\r\n",
+        ]);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].name, "echo hi");
+        assert_eq!(commands[0].start, 3);
+        assert_eq!(commands[0].end, Some(3));
+        assert_eq!(commands[1].name, "make");
+        assert_eq!(commands[1].start, 5);
+        assert_eq!(commands[1].end, None);
+    }
+}
@@ -1,29 +1,141 @@
 use textmode::Textmode as _;
 
+// how long each keystroke stays in the keystroke overlay, in recorded time
+const KEY_DISPLAY_TIME: std::time::Duration =
+    std::time::Duration::from_secs(2);
+
 pub struct Display {
     screen: vt100::Screen,
     current_frame: usize,
-    total_frames: usize,
+    time: std::time::Duration,
+    elapsed: std::time::Duration,
+    progress: crate::frames::Progress,
     done_loading: bool,
+    live: bool,
     paused: bool,
     speed: u32,
     show_ui: bool,
     show_help: bool,
+    help: Vec<String>,
     active_search: Option<String>,
+    goto_prompt: Option<String>,
+    offset: (u16, u16),
+    follow_cursor: bool,
+    keys: Option<Vec<ttyrec::Frame>>,
+    show_keys: bool,
+    metadata: Option<ttyrec_bin::metadata::Metadata>,
+    // why the metadata file couldn't be read, if it couldn't
+    metadata_error: Option<String>,
+    show_info: bool,
+    bookmarks: Vec<ttyrec_bin::bookmarks::Bookmark>,
+    bookmarks_error: Option<String>,
+    show_bookmarks: bool,
+    bookmark_prompt: Option<String>,
+    commands: Vec<crate::commands::Command>,
+    show_commands: bool,
+    show_timeline: bool,
+    // whether the mouse button was pressed on the timeline, and is still
+    // being held
+    dragging: bool,
+    // the query that search_hits are for
+    last_search: Option<String>,
+    search_hits: Vec<std::time::Duration>,
+    colors: ttyrec_bin::config::Colors,
+}
+
+/// Describes which part of the recorded screen is visible, and where on the
+/// terminal it is drawn.
+#[derive(Debug, Clone, Copy)]
+struct Viewport {
+    origin: (u16, u16),
+    start: (u16, u16),
+    size: (u16, u16),
+}
+
+impl Viewport {
+    fn new(screen: (u16, u16), term: (u16, u16), offset: (u16, u16)) -> Self {
+        let rows = axis(screen.0, term.0, offset.0);
+        let cols = axis(screen.1, term.1, offset.1);
+        Self {
+            origin: (rows.0, cols.0),
+            start: (rows.1, cols.1),
+            size: (rows.2, cols.2),
+        }
+    }
+
+    fn contains(&self, pos: (u16, u16)) -> bool {
+        (self.start.0..self.start.0 + self.size.0).contains(&pos.0)
+            && (self.start.1..self.start.1 + self.size.1).contains(&pos.1)
+    }
+
+    fn to_term(self, pos: (u16, u16)) -> (u16, u16) {
+        (
+            self.origin.0 + pos.0 - self.start.0,
+            self.origin.1 + pos.1 - self.start.1,
+        )
+    }
+}
+
+// returns the terminal position to draw at, the first visible recorded
+// position, and the number of visible positions along a single axis
+fn axis(screen: u16, term: u16, offset: u16) -> (u16, u16, u16) {
+    if screen <= term {
+        ((term - screen) / 2, 0, screen)
+    } else {
+        (0, offset.min(screen - term), term)
+    }
 }
 
 impl Display {
-    pub fn new() -> Self {
+    pub fn new(
+        live: bool,
+        keys: Option<Vec<ttyrec::Frame>>,
+        metadata: Result<Option<ttyrec_bin::metadata::Metadata>, String>,
+        bookmarks: Result<Vec<ttyrec_bin::bookmarks::Bookmark>, String>,
+        help: Vec<String>,
+        colors: ttyrec_bin::config::Colors,
+    ) -> Self {
+        let (metadata, metadata_error) = match metadata {
+            Ok(metadata) => (metadata, None),
+            Err(e) => (None, Some(e)),
+        };
+        let (bookmarks, bookmarks_error) = match bookmarks {
+            Ok(bookmarks) => (bookmarks, None),
+            Err(e) => (vec![], Some(e)),
+        };
         Self {
             screen: vt100::Parser::default().screen().clone(),
             current_frame: 0,
-            total_frames: 0,
+            time: std::time::Duration::ZERO,
+            elapsed: std::time::Duration::ZERO,
+            progress: crate::frames::Progress::default(),
             done_loading: false,
+            live,
             paused: false,
             speed: 16,
             show_ui: true,
             show_help: false,
+            help,
             active_search: None,
+            goto_prompt: None,
+            offset: (0, 0),
+            follow_cursor: true,
+            show_keys: keys.is_some(),
+            keys,
+            metadata,
+            metadata_error,
+            show_info: false,
+            bookmarks,
+            bookmarks_error,
+            show_bookmarks: false,
+            bookmark_prompt: None,
+            commands: vec![],
+            show_commands: false,
+            show_timeline: false,
+            dragging: false,
+            last_search: None,
+            search_hits: vec![],
+            colors,
         }
     }
 
@@ -35,8 +147,16 @@ impl Display {
         self.current_frame = idx;
     }
 
-    pub fn total_frames(&mut self, n: usize) {
-        self.total_frames = n;
+    pub fn time(&mut self, time: std::time::Duration) {
+        self.time = time;
+    }
+
+    pub fn elapsed(&mut self, elapsed: std::time::Duration) {
+        self.elapsed = elapsed;
+    }
+
+    pub fn progress(&mut self, progress: crate::frames::Progress) {
+        self.progress = progress;
     }
 
     pub fn done_loading(&mut self) {
@@ -67,24 +187,211 @@ impl Display {
         self.active_search = None;
     }
 
+    pub fn run_search(&mut self, s: &str) {
+        if self.last_search.as_deref() != Some(s) {
+            self.last_search = Some(s.to_string());
+            self.search_hits.clear();
+        }
+    }
+
+    pub fn search_hits(
+        &mut self,
+        query: &str,
+        hits: Vec<std::time::Duration>,
+    ) {
+        // ignore results from a search which has since been replaced
+        if self.last_search.as_deref() == Some(query) {
+            self.search_hits = hits;
+        }
+    }
+
+    pub fn toggle_timeline(&mut self) {
+        self.show_timeline = !self.show_timeline;
+    }
+
+    pub fn goto_prompt(&mut self, s: String) {
+        self.goto_prompt = Some(s);
+    }
+
+    pub fn clear_goto_prompt(&mut self) {
+        self.goto_prompt = None;
+    }
+
+    pub fn pan(&mut self, pan: &crate::event::Pan) {
+        // the offset is clamped to the size of the terminal on the next
+        // render
+        let step = |n: u16| (n / 4).max(1);
+        let (rows, cols) = self.screen.size();
+        match pan {
+            crate::event::Pan::Up => {
+                self.offset.0 = self.offset.0.saturating_sub(step(rows));
+            }
+            crate::event::Pan::Down => {
+                self.offset.0 = self.offset.0.saturating_add(step(rows));
+            }
+            crate::event::Pan::Left => {
+                self.offset.1 = self.offset.1.saturating_sub(step(cols));
+            }
+            crate::event::Pan::Right => {
+                self.offset.1 = self.offset.1.saturating_add(step(cols));
+            }
+        }
+        self.follow_cursor = false;
+    }
+
+    pub fn toggle_follow_cursor(&mut self) {
+        self.follow_cursor = !self.follow_cursor;
+    }
+
+    pub fn toggle_keys(&mut self) {
+        self.show_keys = !self.show_keys;
+    }
+
+    pub fn toggle_info(&mut self) {
+        self.show_info = !self.show_info;
+    }
+
+    pub fn toggle_bookmarks(&mut self) {
+        self.show_bookmarks = !self.show_bookmarks;
+    }
+
+    pub fn bookmark_prompt(&mut self, s: String) {
+        self.bookmark_prompt = Some(s);
+    }
+
+    pub fn clear_bookmark_prompt(&mut self) {
+        self.bookmark_prompt = None;
+    }
+
+    pub fn commands(&mut self, commands: Vec<crate::commands::Command>) {
+        self.commands = commands;
+    }
+
+    pub fn toggle_commands(&mut self) {
+        self.show_commands = !self.show_commands;
+    }
+
+    pub fn bookmark_times(&self) -> Vec<std::time::Duration> {
+        self.bookmarks.iter().map(bookmark_time).collect()
+    }
+
+    /// Adds a bookmark at the current frame, returning it so that it can be
+    /// saved.
+    pub fn add_bookmark(
+        &mut self,
+        name: String,
+    ) -> ttyrec_bin::bookmarks::Bookmark {
+        let name = if name.trim().is_empty() {
+            format!("bookmark {}", self.bookmarks.len() + 1)
+        } else {
+            name
+        };
+        let bookmark = ttyrec_bin::bookmarks::Bookmark {
+            time: self.time.as_secs_f64(),
+            name,
+        };
+        let idx = self
+            .bookmarks
+            .partition_point(|other| other.time <= bookmark.time);
+        self.bookmarks.insert(idx, bookmark.clone());
+        bookmark
+    }
+
+    /// Returns what to do in response to a mouse event, given the size of
+    /// the terminal.
+    pub fn mouse(
+        &mut self,
+        size: (u16, u16),
+        mouse: crate::mouse::Mouse,
+    ) -> Option<crate::event::TimerAction> {
+        let (row, col) = mouse.pos;
+        match (mouse.button, mouse.kind) {
+            (crate::mouse::Button::WheelUp, crate::mouse::Kind::Press) => {
+                Some(crate::event::TimerAction::PreviousFrame)
+            }
+            (crate::mouse::Button::WheelDown, crate::mouse::Kind::Press) => {
+                Some(crate::event::TimerAction::NextFrame)
+            }
+            (crate::mouse::Button::Left, crate::mouse::Kind::Press) => {
+                // clicking the pause symbol unpauses, but it's only there
+                // while paused with the ui shown
+                if self.paused
+                    && self.show_ui
+                    && (row, col) == (0, size.1.saturating_sub(1))
+                {
+                    Some(crate::event::TimerAction::Pause)
+                } else if self.timeline_visible()
+                    && row == size.0.saturating_sub(1)
+                {
+                    let (start, _, _) = self.timeline_bar(size)?;
+                    if col < start {
+                        return None;
+                    }
+                    self.dragging = true;
+                    self.timeline_seek(size, col)
+                } else {
+                    None
+                }
+            }
+            (crate::mouse::Button::Left, crate::mouse::Kind::Drag) => {
+                if self.dragging {
+                    self.timeline_seek(size, col)
+                } else {
+                    None
+                }
+            }
+            (crate::mouse::Button::Left, crate::mouse::Kind::Release) => {
+                self.dragging = false;
+                None
+            }
+            _ => None,
+        }
+    }
+
     pub async fn render(
-        &self,
+        &mut self,
         output: &mut textmode::Output,
     ) -> anyhow::Result<()> {
-        self.render_screen(output);
+        let viewport = self.viewport(output.screen().size());
+        self.render_screen(output, viewport);
         let pos = output.screen().cursor_position();
 
+        if self.show_keys {
+            self.render_keys(output);
+        }
+
+        if self.timeline_visible() {
+            self.render_timeline(output);
+        }
+
         if self.paused && self.show_ui {
             self.render_frame_count(output);
             self.render_speed(output);
             self.render_pause_symbol(output);
+            self.render_viewport(output, viewport);
 
             if self.show_help {
                 self.render_help(output);
             }
+        } else if self.live && self.show_ui {
+            self.render_live_marker(output);
+        }
+
+        if self.show_info {
+            self.render_info(output);
+        }
+
+        if self.show_bookmarks {
+            self.render_bookmarks(output);
+        }
+
+        if self.show_commands {
+            self.render_commands(output);
         }
 
         self.render_search(output);
+        self.render_goto_prompt(output);
+        self.render_bookmark_prompt(output);
 
         output.reset_attributes();
         output.move_to(pos.0, pos.1);
@@ -93,34 +400,280 @@ impl Display {
         Ok(())
     }
 
-    fn render_screen(&self, output: &mut textmode::Output) {
+    fn viewport(&mut self, term: (u16, u16)) -> Viewport {
+        let screen = self.screen.size();
+        if self.follow_cursor {
+            let cursor = self.screen.cursor_position();
+            for (offset, cursor, screen, term) in [
+                (&mut self.offset.0, cursor.0, screen.0, term.0),
+                (&mut self.offset.1, cursor.1, screen.1, term.1),
+            ] {
+                let cursor = cursor.min(screen.saturating_sub(1));
+                if cursor < *offset {
+                    *offset = cursor;
+                } else if cursor >= *offset + term {
+                    *offset = cursor + 1 - term;
+                }
+            }
+        }
+        self.offset = (
+            self.offset.0.min(screen.0.saturating_sub(term.0)),
+            self.offset.1.min(screen.1.saturating_sub(term.1)),
+        );
+        Viewport::new(screen, term, self.offset)
+    }
+
+    fn render_screen(
+        &self,
+        output: &mut textmode::Output,
+        viewport: Viewport,
+    ) {
         output.clear();
-        output.move_to(0, 0);
-        output.write(&self.screen.contents_formatted());
+        self.render_border(output, viewport);
+        for (i, row) in self
+            .screen
+            .rows_formatted(viewport.start.1, viewport.size.1)
+            .enumerate()
+            .skip(usize::from(viewport.start.0))
+            .take(usize::from(viewport.size.0))
+        {
+            // rows_formatted can never return more than u16::MAX rows
+            let i = u16::try_from(i).unwrap();
+            output.reset_attributes();
+            output.move_to(
+                viewport.origin.0 + i - viewport.start.0,
+                viewport.origin.1,
+            );
+            output.write(&row);
+        }
+
+        let (rows, cols) = self.screen.size();
+        let cursor = self.screen.cursor_position();
+        let cursor = (
+            cursor.0.min(rows.saturating_sub(1)),
+            cursor.1.min(cols.saturating_sub(1)),
+        );
+        if viewport.contains(cursor) {
+            let (row, col) = viewport.to_term(cursor);
+            output.move_to(row, col);
+            output.hide_cursor(self.screen.hide_cursor());
+        } else {
+            output.hide_cursor(true);
+        }
+    }
+
+    #[allow(clippy::unused_self)]
+    fn render_border(
+        &self,
+        output: &mut textmode::Output,
+        viewport: Viewport,
+    ) {
+        let Viewport { origin, size, .. } = viewport;
+        let term = output.screen().size();
+        let top = origin.0.checked_sub(1);
+        let bottom = Some(origin.0 + size.0).filter(|&row| row < term.0);
+        let left = origin.1.checked_sub(1);
+        let right = Some(origin.1 + size.1).filter(|&col| col < term.1);
+
+        output.reset_attributes();
+        for (row, corners) in [
+            (top, ("\u{250c}", "\u{2510}")),
+            (bottom, ("\u{2514}", "\u{2518}")),
+        ] {
+            let Some(row) = row else { continue };
+            if let Some(left) = left {
+                output.move_to(row, left);
+                output.write_str(corners.0);
+            } else {
+                output.move_to(row, origin.1);
+            }
+            output.write_str(&"\u{2500}".repeat(usize::from(size.1)));
+            if right.is_some() {
+                output.write_str(corners.1);
+            }
+        }
+        for col in [left, right].into_iter().flatten() {
+            for row in origin.0..origin.0 + size.0 {
+                output.move_to(row, col);
+                output.write_str("\u{2502}");
+            }
+        }
     }
 
     fn render_frame_count(&self, output: &mut textmode::Output) {
         output.move_to(0, 0);
         output.reset_attributes();
-        output.set_fgcolor(textmode::color::BLACK);
+        output.set_fgcolor(color(self.colors.text));
+        // time spent paused while recording isn't in the recording itself
+        let paused = self
+            .metadata
+            .as_ref()
+            .map_or(std::time::Duration::ZERO, |metadata| {
+                metadata.paused_before(self.time)
+            });
+        let wall_clock = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.start_time)
+            .or_else(|| self.progress.start_time.map(|time| time.as_secs()))
+            .map(|start| {
+                format!(
+                    "{} ",
+                    ttyrec_bin::metadata::format_time(start.saturating_add(
+                        self.time.saturating_add(paused).as_secs()
+                    ))
+                )
+            })
+            .unwrap_or_default();
+        if self.live {
+            // a live recording is never done loading, so show whether we
+            // are caught up instead
+            output.set_bgcolor(self.live_color());
+            output.write_str(&format!(
+                " {}/{} {wall_clock}\u{25cf} live ",
+                self.current_frame + 1,
+                self.progress.count
+            ));
+            return;
+        }
         if self.done_loading {
-            output.set_bgcolor(textmode::color::CYAN);
+            output.set_bgcolor(color(self.colors.background));
         } else {
-            output.set_bgcolor(textmode::color::RED);
+            output.set_bgcolor(color(self.colors.highlight));
         }
         output.write_str(&format!(
-            " {}/{} ",
+            " {}/{} {wall_clock}",
             self.current_frame + 1,
-            self.total_frames
+            self.progress.count
         ));
     }
 
+    // shows whether playback of a live recording is caught up, while the
+    // frame count (which also shows this) is hidden
+    fn render_live_marker(&self, output: &mut textmode::Output) {
+        let label = " \u{25cf} live ";
+        let len = u16::try_from(label.chars().count()).unwrap();
+        output.move_to(0, output.screen().size().1.saturating_sub(len));
+        output.reset_attributes();
+        output.set_fgcolor(color(self.colors.text));
+        output.set_bgcolor(self.live_color());
+        output.write_str(label);
+    }
+
+    fn live_color(&self) -> textmode::Color {
+        if self.current_frame + 1 >= self.progress.count {
+            color(self.colors.live)
+        } else {
+            color(self.colors.behind)
+        }
+    }
+
+    // draws the elapsed and total time along the bottom row, followed by a
+    // bar showing how much of the recording has been loaded, and where the
+    // current frame, bookmarks and search matches are within it
+    fn render_timeline(&self, output: &mut textmode::Output) {
+        let size = output.screen().size();
+        let row = size.0 - 1;
+        output.move_to(row, 0);
+        output.reset_attributes();
+        output.set_fgcolor(color(self.colors.text));
+        output.set_bgcolor(color(self.colors.background));
+        output.write_str(&self.timeline_label());
+
+        let Some((start, width, total)) = self.timeline_bar(size) else {
+            return;
+        };
+        let col = |time: std::time::Duration| {
+            let last = u128::from(width - 1);
+            let col = (time.as_nanos() * last / total).min(last);
+            // col is at most width - 1
+            start + u16::try_from(col).unwrap()
+        };
+
+        output.reset_attributes();
+        output.set_fgcolor(color(self.colors.background));
+        let loaded_end = col(self.progress.duration) + 1;
+        output.write_str(&"\u{2501}".repeat(usize::from(loaded_end - start)));
+        output.set_fgcolor(textmode::color::DARKGREY);
+        output.write_str(
+            &"\u{2500}".repeat(usize::from(start + width - loaded_end)),
+        );
+        for (times, color, symbol) in [
+            (
+                self.search_hits.clone(),
+                color(self.colors.search),
+                "\u{2022}",
+            ),
+            (
+                self.bookmark_times(),
+                color(self.colors.bookmark),
+                "\u{25c6}",
+            ),
+            (vec![self.time], color(self.colors.position), "\u{25cf}"),
+        ] {
+            output.set_fgcolor(color);
+            for time in times {
+                output.move_to(row, col(time));
+                output.write_str(symbol);
+            }
+        }
+    }
+
+    fn timeline_visible(&self) -> bool {
+        (self.paused && self.show_ui) || self.show_timeline
+    }
+
+    fn timeline_label(&self) -> String {
+        format!(
+            " {} / {}{} ",
+            crate::time::format(self.elapsed),
+            crate::time::format(self.progress.delay),
+            if self.done_loading { "" } else { "+" },
+        )
+    }
+
+    // returns the column the timeline bar starts at, its width, and the
+    // length of the recording (in nanoseconds) that it covers, if there is
+    // room to draw it
+    fn timeline_bar(&self, size: (u16, u16)) -> Option<(u16, u16, u128)> {
+        let start = u16::try_from(self.timeline_label().chars().count())
+            .unwrap_or(u16::MAX);
+        let width = size.1.saturating_sub(start);
+        let loaded = self.progress.duration.as_nanos();
+        // while loading, estimate the length of the whole recording from
+        // how much of the file has been read so far
+        let total = match self.progress.loaded_bytes {
+            Some((read, len)) if !self.done_loading && read > 0 => {
+                loaded * u128::from(len) / u128::from(read)
+            }
+            _ => loaded,
+        };
+        (width >= 10 && total > 0).then_some((start, width, total))
+    }
+
+    // returns the action to seek to the time at column `col` of the
+    // timeline
+    fn timeline_seek(
+        &self,
+        size: (u16, u16),
+        col: u16,
+    ) -> Option<crate::event::TimerAction> {
+        let (start, width, total) = self.timeline_bar(size)?;
+        let col = col.clamp(start, start + width - 1) - start;
+        let nanos = u128::from(col) * total / u128::from(width - 1);
+        Some(crate::event::TimerAction::Seek(
+            crate::event::Seek::Timeline(std::time::Duration::from_nanos(
+                u64::try_from(nanos).unwrap_or(u64::MAX),
+            )),
+        ))
+    }
+
     fn render_speed(&self, output: &mut textmode::Output) {
         if self.speed != 16 {
             output.move_to(1, 0);
             output.reset_attributes();
-            output.set_fgcolor(textmode::color::BLACK);
-            output.set_bgcolor(textmode::color::CYAN);
+            output.set_fgcolor(color(self.colors.text));
+            output.set_bgcolor(color(self.colors.background));
 
             output.write_str(&format!(
                 "speed: {}x",
@@ -129,68 +682,345 @@ impl Display {
         }
     }
 
-    #[allow(clippy::unused_self)]
     fn render_pause_symbol(&self, output: &mut textmode::Output) {
         let size = output.screen().size();
         output.move_to(0, size.1 - 1);
         output.reset_attributes();
-        output.set_fgcolor(textmode::color::BLACK);
-        output.set_bgcolor(textmode::color::RED);
+        output.set_fgcolor(color(self.colors.text));
+        output.set_bgcolor(color(self.colors.highlight));
         output.write_str("\u{23f8}");
     }
 
-    #[allow(clippy::unused_self)]
     fn render_help(&self, output: &mut textmode::Output) {
         let size = output.screen().size();
         output.reset_attributes();
-        output.set_fgcolor(textmode::color::BLACK);
-        output.set_bgcolor(textmode::color::CYAN);
-
-        output.move_to(size.0 - 16, size.1 - 23);
-        output.write_str("         keys          ");
-        output.move_to(size.0 - 15, size.1 - 23);
-        output.write_str(" q:     quit           ");
-        output.move_to(size.0 - 14, size.1 - 23);
-        output.write_str(" space: pause/unpause  ");
-        output.move_to(size.0 - 13, size.1 - 23);
-        output.write_str(" tab:   hide/show ui   ");
-        output.move_to(size.0 - 12, size.1 - 23);
-        output.write_str(" h:     previous frame ");
-        output.move_to(size.0 - 11, size.1 - 23);
-        output.write_str(" l:     next frame     ");
-        output.move_to(size.0 - 10, size.1 - 23);
-        output.write_str(" 0:     first frame    ");
-        output.move_to(size.0 - 9, size.1 - 23);
-        output.write_str(" $:     last frame     ");
-        output.move_to(size.0 - 8, size.1 - 23);
-        output.write_str(" +:     increase speed ");
-        output.move_to(size.0 - 7, size.1 - 23);
-        output.write_str(" -:     decrease speed ");
-        output.move_to(size.0 - 6, size.1 - 23);
-        output.write_str(" =:     normal speed   ");
-        output.move_to(size.0 - 5, size.1 - 23);
-        output.write_str(" /:     search         ");
-        output.move_to(size.0 - 4, size.1 - 23);
-        output.write_str(" n:     next match     ");
-        output.move_to(size.0 - 3, size.1 - 23);
-        output.write_str(" p:     previous match ");
-        output.move_to(size.0 - 2, size.1 - 23);
-        output.write_str(" ?:     hide/show help ");
+        output.set_fgcolor(color(self.colors.text));
+        output.set_bgcolor(color(self.colors.background));
+
+        // split the list into columns if it doesn't fit on the screen
+        let max_rows = usize::from(size.0).saturating_sub(1).max(1);
+        let columns: Vec<_> = self.help.chunks(max_rows).collect();
+        // every line is padded to the same width
+        let width = self.help.first().map_or(0, |line| line.chars().count());
+        for (i, column) in columns.iter().enumerate() {
+            // the help text is a short list of short lines
+            let rows = u16::try_from(column.len()).unwrap();
+            let left = u16::try_from(width * (columns.len() - i)).unwrap();
+            for (row, line) in
+                (size.0.saturating_sub(rows + 1)..).zip(*column)
+            {
+                output.move_to(row, size.1.saturating_sub(left));
+                output.write_str(line);
+            }
+        }
+    }
+
+    #[allow(clippy::unused_self)]
+    fn render_viewport(
+        &self,
+        output: &mut textmode::Output,
+        viewport: Viewport,
+    ) {
+        let (rows, cols) = self.screen.size();
+        if viewport.size == (rows, cols) {
+            return;
+        }
+
+        let size = output.screen().size();
+        // the timeline is always shown on the bottom row along with this
+        output.move_to(size.0.saturating_sub(2), 0);
+        output.reset_attributes();
+        output.set_fgcolor(color(self.colors.text));
+        output.set_bgcolor(color(self.colors.background));
+        output.write_str(&format!(
+            " rows {}-{}/{}, cols {}-{}/{}{} ",
+            viewport.start.0 + 1,
+            viewport.start.0 + viewport.size.0,
+            rows,
+            viewport.start.1 + 1,
+            viewport.start.1 + viewport.size.1,
+            cols,
+            if self.follow_cursor {
+                " (following cursor)"
+            } else {
+                ""
+            },
+        ));
+    }
+
+    fn render_keys(&self, output: &mut textmode::Output) {
+        let Some(keys) = &self.keys else { return };
+        // keys are sorted by time, so find the ones which were pressed
+        // shortly before the current frame
+        let end = keys.partition_point(|key| key.time <= self.time);
+        let start = keys[..end]
+            .partition_point(|key| key.time + KEY_DISPLAY_TIME <= self.time);
+        let mut text = String::new();
+        for key in keys[start..end]
+            .iter()
+            .flat_map(|key| ttyrec_bin::keys::parse(&key.data))
+        {
+            match key {
+                ttyrec_bin::keys::Key::Char(c) => text.push(c),
+                ttyrec_bin::keys::Key::Named(name) => {
+                    if !text.is_empty() && !text.ends_with(' ') {
+                        text.push(' ');
+                    }
+                    text.push_str(&name);
+                    text.push(' ');
+                }
+            }
+        }
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+
+        // only the most recent keys are shown if they don't all fit
+        let size = output.screen().size();
+        let width = usize::from(size.1).saturating_sub(4);
+        let len = text.chars().count();
+        let text: String =
+            text.chars().skip(len.saturating_sub(width)).collect();
+        let len = u16::try_from(text.chars().count()).unwrap_or(size.1);
+        output.move_to(
+            size.0.saturating_sub(2),
+            size.1.saturating_sub(len + 2) / 2,
+        );
+        output.reset_attributes();
+        output.set_fgcolor(color(self.colors.text));
+        output.set_bgcolor(color(self.colors.keys));
+        output.write_str(&format!(" {text} "));
+    }
+
+    fn render_info(&self, output: &mut textmode::Output) {
+        let mut lines = vec![];
+        if let Some(metadata) = &self.metadata {
+            let mut field = |name: &str, value: Option<String>| {
+                if let Some(value) = value {
+                    lines.push(format!("{name:>9}: {value}"));
+                }
+            };
+            field(
+                "command",
+                (!metadata.command.is_empty())
+                    .then(|| metadata.command.clone()),
+            );
+            field("directory", metadata.cwd.clone());
+            field("term", metadata.term.clone());
+            field("host", metadata.hostname.clone());
+            field("user", metadata.user.clone());
+            field(
+                "started",
+                metadata.start_time.map(ttyrec_bin::metadata::format_time),
+            );
+            field(
+                "size",
+                metadata.size.map(|(rows, cols)| format!("{rows}x{cols}")),
+            );
+            field(
+                "exit",
+                Some(metadata.exit_status.map_or_else(
+                    || {
+                        "unknown (still running when recording stopped)"
+                            .to_string()
+                    },
+                    |status| status.to_string(),
+                )),
+            );
+        } else if let Some(e) = &self.metadata_error {
+            lines.push(format!("couldn't read information: {e}"));
+        } else {
+            lines.push("no information about this recording".to_string());
+        }
+        render_box(output, self.colors, &lines);
+    }
+
+    fn render_bookmarks(&self, output: &mut textmode::Output) {
+        // bookmarks added since then are still shown along with the error
+        let error = self
+            .bookmarks_error
+            .as_ref()
+            .map(|e| format!("couldn't read bookmarks: {e}"));
+        if self.bookmarks.is_empty() {
+            let line = error.unwrap_or_else(|| "no bookmarks".to_string());
+            render_box(output, self.colors, &[line]);
+            return;
+        }
+
+        // the marker shows the most recent bookmark before the current
+        // frame
+        let current = self
+            .bookmarks
+            .partition_point(|bookmark| bookmark_time(bookmark) <= self.time)
+            .checked_sub(1);
+        let lines = error
+            .into_iter()
+            .chain(self.bookmarks.iter().map(|bookmark| {
+                format!(
+                    "{}  {}",
+                    crate::time::format(bookmark_time(bookmark)),
+                    bookmark.name
+                )
+            }))
+            .collect();
+        let current =
+            current.map(|i| i + usize::from(self.bookmarks_error.is_some()));
+        render_list(output, self.colors, lines, current);
+    }
+
+    fn render_commands(&self, output: &mut textmode::Output) {
+        if self.commands.is_empty() {
+            render_box(
+                output,
+                self.colors,
+                &["no commands found".to_string()],
+            );
+            return;
+        }
+
+        let current = self
+            .commands
+            .partition_point(|command| command.start <= self.current_frame)
+            .checked_sub(1);
+        let lines = self
+            .commands
+            .iter()
+            .map(|command| {
+                let exit = command
+                    .exit
+                    .filter(|&exit| exit != 0)
+                    .map(|exit| format!(" (exit {exit})"))
+                    .unwrap_or_default();
+                format!(
+                    "{}  {}{exit}",
+                    crate::time::format(command.time),
+                    command.name
+                )
+            })
+            .collect();
+        render_list(output, self.colors, lines, current);
+    }
+
+    fn render_goto_prompt(&self, output: &mut textmode::Output) {
+        if let Some(position) = &self.goto_prompt {
+            let size = output.screen().size();
+            output.reset_attributes();
+            output.set_fgcolor(color(self.colors.text));
+            output.set_bgcolor(color(self.colors.background));
+
+            let prompt = format!("go to [[h:]m:]s or %: {position}");
+            output.move_to(size.0 - 1, 0);
+            output.write_str(&prompt);
+            output.write_str(&" ".repeat(
+                usize::from(size.1).saturating_sub(prompt.chars().count()),
+            ));
+        }
+    }
+
+    fn render_bookmark_prompt(&self, output: &mut textmode::Output) {
+        if let Some(name) = &self.bookmark_prompt {
+            let size = output.screen().size();
+            output.reset_attributes();
+            output.set_fgcolor(color(self.colors.text));
+            output.set_bgcolor(color(self.colors.bookmark));
+
+            let prompt = format!("bookmark name: {name}");
+            output.move_to(size.0 - 1, 0);
+            output.write_str(&prompt);
+            output.write_str(&" ".repeat(
+                usize::from(size.1).saturating_sub(prompt.chars().count()),
+            ));
+        }
     }
 
     fn render_search(&self, output: &mut textmode::Output) {
         if let Some(search) = &self.active_search {
             let size = output.screen().size();
             output.reset_attributes();
-            output.set_fgcolor(textmode::color::BLACK);
-            output.set_bgcolor(textmode::color::CYAN);
+            output.set_fgcolor(color(self.colors.text));
+            output.set_bgcolor(color(self.colors.background));
 
             output.move_to(size.0 - 1, 0);
             output.write_str("/");
             output.write_str(search);
-            output.write_str(
-                &" ".repeat(usize::from(size.1) - search.len() - 1),
-            );
+            output.write_str(&" ".repeat(
+                usize::from(size.1).saturating_sub(search.len() + 1),
+            ));
         }
     }
 }
+
+// draws `lines` in a box with a marker next to the `current` line, showing
+// only the lines around it if they don't all fit
+fn render_list(
+    output: &mut textmode::Output,
+    colors: ttyrec_bin::config::Colors,
+    lines: Vec<String>,
+    current: Option<usize>,
+) {
+    let mut lines: Vec<String> = lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let marker = if Some(i) == current { '\u{25b6}' } else { ' ' };
+            format!("{marker} {line}")
+        })
+        .collect();
+    let max_rows = usize::from(output.screen().size().0).saturating_sub(2);
+    if lines.len() > max_rows {
+        let skip = current
+            .unwrap_or(0)
+            .saturating_sub(max_rows / 2)
+            .min(lines.len() - max_rows);
+        lines = lines.into_iter().skip(skip).take(max_rows).collect();
+    }
+    render_box(output, colors, &lines);
+}
+
+fn render_box(
+    output: &mut textmode::Output,
+    colors: ttyrec_bin::config::Colors,
+    lines: &[String],
+) {
+    let size = output.screen().size();
+    let max_width = usize::from(size.1).saturating_sub(4);
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|line| line.chars().take(max_width).collect())
+        .collect();
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    // callers never pass more lines than fit on the screen, and each line
+    // is truncated to fit
+    let rows = u16::try_from(lines.len()).unwrap();
+    let cols = u16::try_from(width + 2).unwrap();
+    let top = size.0.saturating_sub(rows) / 2;
+    let left = size.1.saturating_sub(cols) / 2;
+
+    output.reset_attributes();
+    output.set_fgcolor(color(colors.text));
+    output.set_bgcolor(color(colors.background));
+    for (row, line) in (top..).zip(&lines) {
+        output.move_to(row, left);
+        output.write_str(&format!(" {line:width$} "));
+    }
+}
+
+fn color(color: ttyrec_bin::config::Color) -> textmode::Color {
+    match color {
+        ttyrec_bin::config::Color::Idx(idx) => textmode::Color::Idx(idx),
+        ttyrec_bin::config::Color::Rgb(r, g, b) => {
+            textmode::Color::Rgb(r, g, b)
+        }
+    }
+}
+
+fn bookmark_time(
+    bookmark: &ttyrec_bin::bookmarks::Bookmark,
+) -> std::time::Duration {
+    std::time::Duration::try_from_secs_f64(bookmark.time).unwrap_or_default()
+}
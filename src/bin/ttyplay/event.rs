@@ -1,15 +1,33 @@
+use textmode::Textmode as _;
+
 #[derive(Debug)]
 pub enum Event {
-    FrameTransition((usize, Box<vt100::Screen>)),
-    FrameLoaded(Option<usize>),
+    FrameTransition((usize, Box<crate::frames::Frame>)),
+    FrameLoaded(Option<crate::frames::Progress>),
+    Commands(Vec<crate::commands::Command>),
     Paused(bool),
     Speed(u32),
     TimerAction(TimerAction),
     ToggleUi,
     ToggleHelp,
+    Pan(Pan),
+    ToggleFollowCursor,
+    ToggleKeys,
+    ToggleInfo,
+    ToggleBookmarks,
+    BookmarkPrompt(String),
+    Bookmark(BookmarkAction),
+    ToggleCommands,
+    Resize((u16, u16)),
     ActiveSearch(String),
     CancelSearch,
     RunSearch(String, bool),
+    SearchHits(String, Vec<std::time::Duration>),
+    ToggleTimeline,
+    GotoPrompt(String),
+    CancelGoto,
+    RunGoto(crate::time::Position),
+    Mouse(crate::mouse::Mouse),
     Error(anyhow::Error),
     Quit,
 }
@@ -25,9 +43,41 @@ pub enum TimerAction {
     SlowDown,
     DefaultSpeed,
     Search(String, bool),
+    // jump to the frame for the next (or previous, if the flag is set) of
+    // these times
+    Jump(Vec<std::time::Duration>, bool),
+    // jump to the start of the next (or previous) command
+    JumpCommand(bool),
+    Seek(Seek),
     Quit,
 }
 
+#[derive(Debug)]
+pub enum Seek {
+    Forward(std::time::Duration),
+    Backward(std::time::Duration),
+    To(crate::time::Position),
+    // a time relative to the first frame, like the positions of bookmarks
+    // on the timeline
+    Timeline(std::time::Duration),
+}
+
+#[derive(Debug)]
+pub enum BookmarkAction {
+    Next,
+    Previous,
+    Add(String),
+    Cancel,
+}
+
+#[derive(Debug)]
+pub enum Pan {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
 struct Reader {
     pending: tokio::sync::Mutex<Pending>,
     // XXX not quite a condvar, but i think close enough as long as there is
@@ -69,25 +119,40 @@ impl Reader {
     }
 
     async fn event(&self, event: Event) {
-        let mut pending = self.pending.lock().await;
-        pending.event(event);
+        self.pending.lock().await.event(event);
         self.cvar.notify_one();
     }
 }
 
 #[derive(Default)]
 struct Pending {
-    render: Option<(usize, Box<vt100::Screen>)>,
-    frame_loaded: Option<usize>,
+    render: Option<(usize, Box<crate::frames::Frame>)>,
+    frame_loaded: Option<crate::frames::Progress>,
     done_loading: bool,
+    commands: Option<Vec<crate::commands::Command>>,
     paused: Option<bool>,
     speed: Option<u32>,
     timer_actions: std::collections::VecDeque<TimerAction>,
     toggle_ui: bool,
     toggle_help: bool,
+    pans: std::collections::VecDeque<Pan>,
+    toggle_follow_cursor: bool,
+    toggle_keys: bool,
+    toggle_info: bool,
+    toggle_bookmarks: bool,
+    bookmark_prompt: Option<String>,
+    bookmark_actions: std::collections::VecDeque<BookmarkAction>,
+    toggle_commands: bool,
+    resize: Option<(u16, u16)>,
     active_search: Option<String>,
     cancel_search: bool,
     run_search: Option<(String, bool)>,
+    search_hits: Option<(String, Vec<std::time::Duration>)>,
+    toggle_timeline: bool,
+    goto_prompt: Option<String>,
+    cancel_goto: bool,
+    run_goto: Option<crate::time::Position>,
+    mouse: std::collections::VecDeque<crate::mouse::Mouse>,
     error: Option<anyhow::Error>,
     quit: bool,
 }
@@ -99,8 +164,8 @@ impl Pending {
 
     fn event(&mut self, event: Event) {
         match event {
-            Event::FrameTransition((idx, screen)) => {
-                self.render = Some((idx, screen));
+            Event::FrameTransition((idx, frame)) => {
+                self.render = Some((idx, frame));
             }
             Event::FrameLoaded(idx) => {
                 if let Some(idx) = idx {
@@ -109,6 +174,9 @@ impl Pending {
                     self.done_loading = true;
                 }
             }
+            Event::Commands(commands) => {
+                self.commands = Some(commands);
+            }
             Event::Paused(paused) => {
                 self.paused = Some(paused);
             }
@@ -124,6 +192,39 @@ impl Pending {
             Event::ToggleHelp => {
                 self.toggle_help = !self.toggle_help;
             }
+            Event::Pan(pan) => {
+                self.pans.push_back(pan);
+            }
+            Event::ToggleFollowCursor => {
+                self.toggle_follow_cursor = !self.toggle_follow_cursor;
+            }
+            Event::ToggleKeys => {
+                self.toggle_keys = !self.toggle_keys;
+            }
+            Event::ToggleInfo => {
+                self.toggle_info = !self.toggle_info;
+            }
+            Event::ToggleBookmarks => {
+                self.toggle_bookmarks = !self.toggle_bookmarks;
+            }
+            Event::BookmarkPrompt(s) => {
+                self.bookmark_prompt = Some(s);
+            }
+            Event::Bookmark(action) => {
+                if matches!(
+                    action,
+                    BookmarkAction::Add(_) | BookmarkAction::Cancel
+                ) {
+                    self.bookmark_prompt = None;
+                }
+                self.bookmark_actions.push_back(action);
+            }
+            Event::ToggleCommands => {
+                self.toggle_commands = !self.toggle_commands;
+            }
+            Event::Resize(size) => {
+                self.resize = Some(size);
+            }
             Event::ActiveSearch(s) => {
                 self.active_search = Some(s);
                 self.cancel_search = false;
@@ -139,6 +240,38 @@ impl Pending {
                 self.cancel_search = false;
                 self.run_search = Some((s, backwards));
             }
+            Event::SearchHits(query, hits) => {
+                self.search_hits = Some((query, hits));
+            }
+            Event::ToggleTimeline => {
+                self.toggle_timeline = !self.toggle_timeline;
+            }
+            Event::GotoPrompt(s) => {
+                self.goto_prompt = Some(s);
+                self.cancel_goto = false;
+                self.run_goto = None;
+            }
+            Event::CancelGoto => {
+                self.goto_prompt = None;
+                self.cancel_goto = true;
+                self.run_goto = None;
+            }
+            Event::RunGoto(position) => {
+                self.goto_prompt = None;
+                self.cancel_goto = false;
+                self.run_goto = Some(position);
+            }
+            Event::Mouse(mouse) => {
+                // only the most recent position matters while dragging
+                if mouse.kind == crate::mouse::Kind::Drag
+                    && self.mouse.back().is_some_and(|prev| {
+                        prev.kind == crate::mouse::Kind::Drag
+                    })
+                {
+                    self.mouse.pop_back();
+                }
+                self.mouse.push_back(mouse);
+            }
             Event::Error(e) => {
                 self.error = Some(e);
             }
@@ -152,14 +285,30 @@ impl Pending {
         self.render.is_some()
             || self.frame_loaded.is_some()
             || self.done_loading
+            || self.commands.is_some()
             || self.paused.is_some()
             || self.speed.is_some()
             || !self.timer_actions.is_empty()
             || self.toggle_ui
             || self.toggle_help
+            || !self.pans.is_empty()
+            || self.toggle_follow_cursor
+            || self.toggle_keys
+            || self.toggle_info
+            || self.toggle_bookmarks
+            || self.bookmark_prompt.is_some()
+            || !self.bookmark_actions.is_empty()
+            || self.toggle_commands
+            || self.resize.is_some()
             || self.active_search.is_some()
             || self.cancel_search
             || self.run_search.is_some()
+            || self.search_hits.is_some()
+            || self.toggle_timeline
+            || self.goto_prompt.is_some()
+            || self.cancel_goto
+            || self.run_goto.is_some()
+            || !self.mouse.is_empty()
             || self.error.is_some()
             || self.quit
     }
@@ -172,6 +321,10 @@ impl Pending {
             Some(Event::Quit)
         } else if let Some(action) = self.timer_actions.pop_front() {
             Some(Event::TimerAction(action))
+        } else if let Some(mouse) = self.mouse.pop_front() {
+            Some(Event::Mouse(mouse))
+        } else if let Some(size) = self.resize.take() {
+            Some(Event::Resize(size))
         } else if let Some(active_search) = self.active_search.take() {
             Some(Event::ActiveSearch(active_search))
         } else if self.cancel_search {
@@ -179,12 +332,45 @@ impl Pending {
             Some(Event::CancelSearch)
         } else if let Some((run_search, backwards)) = self.run_search.take() {
             Some(Event::RunSearch(run_search, backwards))
+        } else if let Some((query, hits)) = self.search_hits.take() {
+            Some(Event::SearchHits(query, hits))
+        } else if self.toggle_timeline {
+            self.toggle_timeline = false;
+            Some(Event::ToggleTimeline)
+        } else if let Some(goto_prompt) = self.goto_prompt.take() {
+            Some(Event::GotoPrompt(goto_prompt))
+        } else if self.cancel_goto {
+            self.cancel_goto = false;
+            Some(Event::CancelGoto)
+        } else if let Some(position) = self.run_goto.take() {
+            Some(Event::RunGoto(position))
+        } else if let Some(prompt) = self.bookmark_prompt.take() {
+            Some(Event::BookmarkPrompt(prompt))
+        } else if let Some(action) = self.bookmark_actions.pop_front() {
+            Some(Event::Bookmark(action))
         } else if self.toggle_ui {
             self.toggle_ui = false;
             Some(Event::ToggleUi)
         } else if self.toggle_help {
             self.toggle_help = false;
             Some(Event::ToggleHelp)
+        } else if let Some(pan) = self.pans.pop_front() {
+            Some(Event::Pan(pan))
+        } else if self.toggle_follow_cursor {
+            self.toggle_follow_cursor = false;
+            Some(Event::ToggleFollowCursor)
+        } else if self.toggle_keys {
+            self.toggle_keys = false;
+            Some(Event::ToggleKeys)
+        } else if self.toggle_info {
+            self.toggle_info = false;
+            Some(Event::ToggleInfo)
+        } else if self.toggle_bookmarks {
+            self.toggle_bookmarks = false;
+            Some(Event::ToggleBookmarks)
+        } else if self.toggle_commands {
+            self.toggle_commands = false;
+            Some(Event::ToggleCommands)
         } else if let Some(paused) = self.paused.take() {
             Some(Event::Paused(paused))
         } else if let Some(speed) = self.speed.take() {
@@ -194,8 +380,10 @@ impl Pending {
         } else if self.done_loading {
             self.done_loading = false;
             Some(Event::FrameLoaded(None))
-        } else if let Some((idx, screen)) = self.render.take() {
-            Some(Event::FrameTransition((idx, screen)))
+        } else if let Some(commands) = self.commands.take() {
+            Some(Event::Commands(commands))
+        } else if let Some((idx, frame)) = self.render.take() {
+            Some(Event::FrameTransition((idx, frame)))
         } else {
             None
         }
@@ -206,8 +394,17 @@ pub async fn handle_events(
     event_r: tokio::sync::mpsc::UnboundedReceiver<Event>,
     timer_w: tokio::sync::mpsc::UnboundedSender<TimerAction>,
     mut output: textmode::Output,
+    live: bool,
+    keys: Option<Vec<ttyrec::Frame>>,
+    metadata: Result<Option<ttyrec_bin::metadata::Metadata>, String>,
+    bookmarks: Result<Vec<ttyrec_bin::bookmarks::Bookmark>, String>,
+    bookmarks_path: Option<std::path::PathBuf>,
+    help: Vec<String>,
+    colors: ttyrec_bin::config::Colors,
 ) -> anyhow::Result<()> {
-    let mut display = crate::display::Display::new();
+    let mut display = crate::display::Display::new(
+        live, keys, metadata, bookmarks, help, colors,
+    );
     let events = Reader::new(event_r);
     while let Some(event) = events.read().await {
         match event {
@@ -215,17 +412,22 @@ pub async fn handle_events(
                 timer_w.send(action)?;
                 continue;
             }
-            Event::FrameTransition((idx, screen)) => {
-                display.screen(*screen);
+            Event::FrameTransition((idx, frame)) => {
+                display.time(frame.time());
+                display.elapsed(frame.delay());
+                display.screen(frame.into_screen());
                 display.current_frame(idx);
             }
             Event::FrameLoaded(n) => {
-                if let Some(n) = n {
-                    display.total_frames(n);
+                if let Some(progress) = n {
+                    display.progress(progress);
                 } else {
                     display.done_loading();
                 }
             }
+            Event::Commands(commands) => {
+                display.commands(commands);
+            }
             Event::Paused(paused) => {
                 display.paused(paused);
             }
@@ -238,6 +440,56 @@ pub async fn handle_events(
             Event::ToggleHelp => {
                 display.toggle_help();
             }
+            Event::Pan(pan) => {
+                display.pan(&pan);
+            }
+            Event::ToggleFollowCursor => {
+                display.toggle_follow_cursor();
+            }
+            Event::ToggleKeys => {
+                display.toggle_keys();
+            }
+            Event::ToggleInfo => {
+                display.toggle_info();
+            }
+            Event::ToggleBookmarks => {
+                display.toggle_bookmarks();
+            }
+            Event::BookmarkPrompt(s) => {
+                display.bookmark_prompt(s);
+            }
+            Event::Bookmark(action) => match action {
+                BookmarkAction::Next | BookmarkAction::Previous => {
+                    timer_w.send(TimerAction::Jump(
+                        display.bookmark_times(),
+                        matches!(action, BookmarkAction::Previous),
+                    ))?;
+                    continue;
+                }
+                BookmarkAction::Add(name) => {
+                    display.clear_bookmark_prompt();
+                    // bookmarks can't be saved for broadcasts
+                    if let Some(path) = &bookmarks_path {
+                        let bookmark = display.add_bookmark(name);
+                        ttyrec_bin::bookmarks::append(path, &bookmark)
+                            .await?;
+                    }
+                }
+                BookmarkAction::Cancel => {
+                    display.clear_bookmark_prompt();
+                }
+            },
+            Event::ToggleCommands => {
+                display.toggle_commands();
+            }
+            Event::Resize((rows, cols)) => {
+                output.set_size(rows, cols);
+                display.render(&mut output).await?;
+                // the terminal may have reflowed or cleared its contents
+                // when it was resized, so we can't trust a diff here
+                output.hard_refresh().await?;
+                continue;
+            }
             Event::ActiveSearch(s) => {
                 display.active_search(s);
             }
@@ -246,8 +498,33 @@ pub async fn handle_events(
             }
             Event::RunSearch(s, backwards) => {
                 display.clear_search();
+                display.run_search(&s);
                 timer_w.send(TimerAction::Search(s, backwards))?;
             }
+            Event::SearchHits(query, hits) => {
+                display.search_hits(&query, hits);
+            }
+            Event::ToggleTimeline => {
+                display.toggle_timeline();
+            }
+            Event::GotoPrompt(s) => {
+                display.goto_prompt(s);
+            }
+            Event::CancelGoto => {
+                display.clear_goto_prompt();
+            }
+            Event::RunGoto(position) => {
+                display.clear_goto_prompt();
+                timer_w.send(TimerAction::Seek(Seek::To(position)))?;
+            }
+            Event::Mouse(mouse) => {
+                if let Some(action) =
+                    display.mouse(output.screen().size(), mouse)
+                {
+                    timer_w.send(action)?;
+                }
+                continue;
+            }
             Event::Error(e) => {
                 return Err(e);
             }
@@ -1,12 +1,23 @@
+use std::os::unix::fs::FileExt as _;
+
 #[derive(Debug, Clone)]
 pub struct Frame {
     screen: vt100::Screen,
     delay: std::time::Duration,
+    time: std::time::Duration,
 }
 
 impl Frame {
-    pub fn new(screen: vt100::Screen, delay: std::time::Duration) -> Self {
-        Self { screen, delay }
+    pub fn new(
+        screen: vt100::Screen,
+        delay: std::time::Duration,
+        time: std::time::Duration,
+    ) -> Self {
+        Self {
+            screen,
+            delay,
+            time,
+        }
     }
 
     pub fn into_screen(self) -> vt100::Screen {
@@ -16,70 +27,400 @@ impl Frame {
     pub fn delay(&self) -> std::time::Duration {
         self.delay
     }
+
+    /// The time the frame was recorded at, relative to the first frame.
+    pub fn time(&self) -> std::time::Duration {
+        self.time
+    }
+}
+
+struct FrameBytes {
+    // frames loaded from a saved index are read from the file on demand
+    data: Option<Vec<u8>>,
+    offset: u64,
+    len: u32,
+    time: std::time::Duration,
+    delay: std::time::Duration,
+}
+
+// the screen contents before replaying frames starting at `next`
+struct Keyframe {
+    next: usize,
+    screen: vt100::Screen,
+}
+
+// timestamps after this (the start of 2000) are assumed to be the actual
+// time of day rather than the time since the recording started
+const WALL_CLOCK_CUTOFF: std::time::Duration =
+    std::time::Duration::from_hours(262_968);
+
+/// How much of a recording has been loaded so far.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub count: usize,
+    /// The time of the last loaded frame, relative to the first frame.
+    pub duration: std::time::Duration,
+    /// How long into playback the last loaded frame is shown.
+    pub delay: std::time::Duration,
+    /// The number of bytes loaded and the total size of the file, if known.
+    pub loaded_bytes: Option<(u64, u64)>,
+    /// When the recording started (as time since the unix epoch), if the
+    /// recording's timestamps are the actual time of day.
+    pub start_time: Option<std::time::Duration>,
 }
 
 pub struct FrameData {
-    frames: Vec<Frame>,
+    frames: Vec<FrameBytes>,
+    keyframes: Vec<Keyframe>,
+    keyframe_interval: usize,
+    max_memory: Option<usize>,
+    keyframe_memory: usize,
+    expected_len: Option<u64>,
+    commands: Vec<crate::commands::Command>,
+    // the most recently reconstructed frame, so that sequential access
+    // doesn't need to go back to a keyframe every time
+    cursor: Option<(usize, vt100::Parser)>,
+    file: Option<std::sync::Arc<std::fs::File>>,
+    sized: bool,
     done_reading: bool,
-    new_frame_w: tokio::sync::watch::Sender<Option<usize>>,
-    new_frame_r: tokio::sync::watch::Receiver<Option<usize>>,
+    // the number of frames read so far, and whether reading is done
+    new_frame_w: tokio::sync::watch::Sender<(usize, bool)>,
+    new_frame_r: tokio::sync::watch::Receiver<(usize, bool)>,
 }
 
 impl FrameData {
-    pub fn new() -> Self {
-        let (new_frame_w, new_frame_r) = tokio::sync::watch::channel(Some(0));
+    pub fn new(
+        size: (u16, u16),
+        keyframe_interval: usize,
+        max_memory: Option<usize>,
+    ) -> Self {
+        let (new_frame_w, new_frame_r) =
+            tokio::sync::watch::channel((0, false));
+        let screen = vt100::Parser::new(size.0, size.1, 0).screen().clone();
         Self {
             frames: vec![],
+            keyframe_memory: screen_memory(&screen),
+            keyframes: vec![Keyframe { next: 0, screen }],
+            keyframe_interval: keyframe_interval.max(1),
+            max_memory,
+            expected_len: None,
+            commands: vec![],
+            cursor: None,
+            file: None,
+            sized: false,
             done_reading: false,
             new_frame_w,
             new_frame_r,
         }
     }
 
-    pub fn get(&self, i: usize) -> Option<&Frame> {
-        self.frames.get(i)
+    pub fn initial_size(&self) -> (u16, u16) {
+        self.keyframes[0].screen.size()
+    }
+
+    /// Returns frame `i`, if it has been loaded.
+    pub async fn get(
+        frames: &std::sync::Arc<tokio::sync::Mutex<Self>>,
+        i: usize,
+    ) -> Option<Frame> {
+        let mut data = frames.clone().lock_owned().await;
+        let delay = data.frames.get(i)?.delay;
+        let time = data.frame_time(i);
+        let (start, mut parser) = data.replay_start(i, true);
+        let sources = data.sources(start..i + 1);
+        // frame data loaded from a saved index is read from the file, which
+        // shouldn't block anything else which needs the lock
+        drop(data);
+        for data in read_sources(sources).await {
+            ttyrec_bin::resize::process(&mut parser, &data);
+        }
+        let screen = parser.screen().clone();
+        frames.lock().await.cursor = Some((i, parser));
+        Some(Frame::new(screen, delay, time))
+    }
+
+    // the time of frame `i`, relative to the first frame
+    fn frame_time(&self, i: usize) -> std::time::Duration {
+        self.frames[i].time.saturating_sub(self.frames[0].time)
+    }
+
+    /// Sets the length of the file being loaded, so that loading progress
+    /// can be reported.
+    pub fn set_expected_len(&mut self, len: u64) {
+        self.expected_len = Some(len);
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            count: self.frames.len(),
+            duration: self.duration(),
+            delay: self
+                .frames
+                .last()
+                .map_or(std::time::Duration::ZERO, |frame| frame.delay),
+            loaded_bytes: self.expected_len.map(|len| {
+                let loaded = self.frames.last().map_or(0, |frame| {
+                    frame.offset
+                        + crate::index::FRAME_HEADER_LEN
+                        + u64::from(frame.len)
+                });
+                (loaded, len)
+            }),
+            // timestamps in ttyrec files recorded by the original ttyrec
+            // are the actual time of day, rather than starting at zero
+            start_time: self
+                .frames
+                .first()
+                .map(|frame| frame.time)
+                .filter(|&time| time >= WALL_CLOCK_CUTOFF),
+        }
     }
 
     pub fn count(&self) -> usize {
         self.frames.len()
     }
 
-    pub fn search(
+    /// The time of the last frame, relative to the first frame.
+    pub fn duration(&self) -> std::time::Duration {
+        match (self.frames.first(), self.frames.last()) {
+            (Some(first), Some(last)) => last.time.saturating_sub(first.time),
+            _ => std::time::Duration::ZERO,
+        }
+    }
+
+    /// Returns the first frame recorded at or after `time` (relative to the
+    /// first frame), or the last frame if there are none.
+    pub fn frame_after_time(&self, time: std::time::Duration) -> usize {
+        let Some(first) = self.frames.first() else {
+            return 0;
+        };
+        self.frames
+            .partition_point(|frame| {
+                frame.time.saturating_sub(first.time) < time
+            })
+            .min(self.frames.len() - 1)
+    }
+
+    /// Returns the frame which is on the screen `delay` after the start of
+    /// playback.
+    pub fn frame_at_delay(&self, delay: std::time::Duration) -> usize {
+        self.frames
+            .partition_point(|frame| frame.delay <= delay)
+            .saturating_sub(1)
+    }
+
+    /// Returns how far into playback `position` is.
+    pub fn position_delay(
         &self,
+        position: crate::time::Position,
+    ) -> std::time::Duration {
+        match position {
+            crate::time::Position::Time(time) => time,
+            crate::time::Position::Percent(percent) => self
+                .frames
+                .last()
+                .map_or(std::time::Duration::ZERO, |frame| frame.delay)
+                .mul_f64(percent / 100.0),
+        }
+    }
+
+    /// Returns whether enough of the recording has been loaded to know
+    /// which frame `position` refers to. Percentages need the whole
+    /// recording, but times only need a frame after them.
+    pub fn position_loaded(&self, position: crate::time::Position) -> bool {
+        self.done_reading
+            || match position {
+                crate::time::Position::Time(time) => {
+                    self.frames.last().is_some_and(|frame| frame.delay > time)
+                }
+                crate::time::Position::Percent(_) => false,
+            }
+    }
+
+    /// Returns the frame to move to from frame `current` for `seek`.
+    pub fn seek(&self, current: usize, seek: &crate::event::Seek) -> usize {
+        let delay = self
+            .frames
+            .get(current)
+            .map_or(std::time::Duration::ZERO, |frame| frame.delay);
+        match seek {
+            crate::event::Seek::Forward(duration) => {
+                self.frame_at_delay(delay + *duration)
+            }
+            crate::event::Seek::Backward(duration) => {
+                self.frame_at_delay(delay.saturating_sub(*duration))
+            }
+            crate::event::Seek::To(position) => {
+                self.frame_at_delay(self.position_delay(*position))
+            }
+            crate::event::Seek::Timeline(time) => {
+                self.frame_after_time(*time)
+            }
+        }
+    }
+
+    /// Returns the next frame after `start` (or the previous frame before it,
+    /// if `backwards` is set) where `query` appears on the screen.
+    pub async fn search(
+        frames: &std::sync::Arc<tokio::sync::Mutex<Self>>,
         start: usize,
         query: &str,
         backwards: bool,
     ) -> Option<usize> {
         if backwards {
-            self.frames
-                .iter()
-                .enumerate()
-                .rev()
-                .skip(self.frames.len() - start + 1)
-                .find(|(_, frame)| frame.screen.contents().contains(query))
-                .map(|(i, _)| i)
+            // replaying is only possible in the forward direction, so search
+            // each keyframe interval separately, starting from the end
+            let mut end = start.saturating_sub(1);
+            while end > 0 {
+                let (next, parser) =
+                    frames.lock().await.replay_start(end - 1, false);
+                let mut found = None;
+                replay(frames, parser, next, Some(end), |i, screen| {
+                    if screen.contents().contains(query) {
+                        found = Some(i);
+                    }
+                    false
+                })
+                .await;
+                if found.is_some() {
+                    return found;
+                }
+                end = next;
+            }
+            None
         } else {
-            self.frames
-                .iter()
-                .enumerate()
-                .skip(start)
-                .find(|(_, frame)| frame.screen.contents().contains(query))
-                .map(|(i, _)| i)
+            let (next, parser) =
+                frames.lock().await.replay_start(start, false);
+            replay(frames, parser, next, None, |i, screen| {
+                i >= start && screen.contents().contains(query)
+            })
+            .await
         }
     }
 
-    pub fn add_frame(&mut self, frame: Frame) {
-        self.frames.push(frame);
+    pub fn commands(&self) -> &[crate::commands::Command] {
+        &self.commands
+    }
+
+    pub fn set_commands(&mut self, commands: Vec<crate::commands::Command>) {
+        self.commands = commands;
+    }
+
+    pub fn add_frame(
+        &mut self,
+        frame: ttyrec::Frame,
+        delay: std::time::Duration,
+        screen: &vt100::Screen,
+    ) {
+        let ttyrec::Frame { time, data } = frame;
+        if self.frames.is_empty() {
+            self.sized = matches!(
+                ttyrec_bin::resize::chunks(&data).next(),
+                Some(ttyrec_bin::resize::Chunk::Resize(_))
+            );
+        }
+        // keyframes can't be created in the middle of an escape sequence,
+        // since the parser state isn't part of the screen
+        let keyframe = self.frames.len() + 1
+            >= self.keyframes.last().unwrap().next + self.keyframe_interval
+            && !ends_in_escape(&data);
+        let offset = self.frames.last().map_or(0, |prev| {
+            prev.offset + crate::index::FRAME_HEADER_LEN + u64::from(prev.len)
+        });
+        self.frames.push(FrameBytes {
+            // ttyrec frame lengths are stored as u32
+            len: u32::try_from(data.len()).unwrap(),
+            data: Some(data),
+            offset,
+            time,
+            delay,
+        });
+        if keyframe {
+            self.keyframe_memory += screen_memory(screen);
+            self.keyframes.push(Keyframe {
+                next: self.frames.len(),
+                screen: screen.clone(),
+            });
+            self.limit_memory();
+        }
+        self.new_frame_w
+            .send((self.frames.len(), false))
+            // new_frame_w is never closed, so this can never fail
+            .unwrap();
+    }
+
+    /// Replaces the contents of this object with the frames described by
+    /// `index`, which will be read from `file` as needed.
+    pub fn load_index(
+        &mut self,
+        index: &crate::index::Index,
+        file: std::fs::File,
+        timing: &mut Timing,
+    ) {
+        self.frames = index
+            .frames
+            .iter()
+            .map(|frame| FrameBytes {
+                data: None,
+                offset: frame.offset,
+                len: frame.len,
+                time: frame.time,
+                delay: timing.delay(frame.time),
+            })
+            .collect();
+        self.keyframes.truncate(1);
+        self.keyframes
+            .extend(index.keyframes.iter().map(|keyframe| Keyframe {
+                next: keyframe.next,
+                screen: restore(keyframe).screen().clone(),
+            }));
+        self.keyframe_memory = self
+            .keyframes
+            .iter()
+            .map(|keyframe| screen_memory(&keyframe.screen))
+            .sum();
+        self.commands.clone_from(&index.commands);
+        self.cursor = None;
+        self.file = Some(std::sync::Arc::new(file));
+        self.sized = index.sized;
         self.new_frame_w
-            .send(Some(self.frames.len()))
+            .send((self.frames.len(), false))
             // new_frame_w is never closed, so this can never fail
             .unwrap();
     }
 
+    /// Returns a description of the loaded frames which can be saved to
+    /// allow the file to be opened more quickly in the future.
+    pub fn to_index(&self) -> crate::index::Index {
+        crate::index::Index {
+            initial_size: self.initial_size(),
+            sized: self.sized,
+            frames: self
+                .frames
+                .iter()
+                .map(|frame| crate::index::IndexedFrame {
+                    offset: frame.offset,
+                    len: frame.len,
+                    time: frame.time,
+                })
+                .collect(),
+            keyframes: self.keyframes[1..]
+                .iter()
+                .map(|keyframe| crate::index::IndexedKeyframe {
+                    next: keyframe.next,
+                    size: keyframe.screen.size(),
+                    alternate_screen: keyframe.screen.alternate_screen(),
+                    state: keyframe.screen.state_formatted(),
+                })
+                .collect(),
+            commands: self.commands.clone(),
+        }
+    }
+
     pub fn done_reading(&mut self) {
         self.done_reading = true;
         self.new_frame_w
-            .send(None)
+            .send((self.frames.len(), true))
             // new_frame_w is never closed, so this can never fail
             .unwrap();
     }
@@ -99,56 +440,447 @@ impl FrameData {
         let mut new_frame_r = self.new_frame_r.clone();
         Box::pin(async move {
             while new_frame_r.changed().await.is_ok() {
-                if let Some(new_len) = *new_frame_r.borrow() {
-                    if i < new_len {
-                        return true;
-                    }
-                } else {
+                // the frame count is sent along with the notification that
+                // reading is done, since a watch channel only keeps the
+                // latest value
+                let (new_len, done) = *new_frame_r.borrow();
+                if i < new_len {
+                    return true;
+                }
+                if done {
                     break;
                 }
             }
             false
         })
     }
+
+    // a parser with the screen contents before frame `i` is replayed, and
+    // the index of the frame it needs to replay next, starting from the
+    // replay cursor if that's closer than the nearest keyframe
+    fn replay_start(
+        &mut self,
+        i: usize,
+        use_cursor: bool,
+    ) -> (usize, vt100::Parser) {
+        let k = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.next <= i)
+            - 1;
+        let next = self.keyframes[k].next;
+        if use_cursor {
+            match self.cursor.take() {
+                Some((cur, parser)) if cur >= next && cur <= i => {
+                    return (cur + 1, parser);
+                }
+                _ => {}
+            }
+        }
+        let screen = &self.keyframes[k].screen;
+        let (rows, cols) = screen.size();
+        let mut parser = vt100::Parser::new(rows, cols, 0);
+        *parser.screen_mut() = screen.clone();
+        (next, parser)
+    }
+
+    fn sources(&self, range: std::ops::Range<usize>) -> Vec<Source> {
+        self.frames[range]
+            .iter()
+            .map(|frame| match (&frame.data, &self.file) {
+                (Some(data), _) => Source::Memory(data.clone()),
+                (None, Some(file)) => Source::File(
+                    file.clone(),
+                    frame.offset + crate::index::FRAME_HEADER_LEN,
+                    frame.len,
+                ),
+                (None, None) => Source::Memory(vec![]),
+            })
+            .collect()
+    }
+
+    // keyframes are what make seeking fast, but they are also what take up
+    // most of the memory, so if they go over the limit, drop every other
+    // keyframe (always keeping the initial state) and start creating them
+    // half as often. the frame data itself isn't counted, since it can't be
+    // dropped without losing the recording.
+    fn limit_memory(&mut self) {
+        let Some(max_memory) = self.max_memory else {
+            return;
+        };
+        while self.keyframe_memory > max_memory && self.keyframes.len() > 1 {
+            let mut i = 0;
+            self.keyframes.retain(|_| {
+                i += 1;
+                i % 2 == 1
+            });
+            // once the interval is longer than the recording, there would
+            // only be one keyframe anyway, so there is no point in making it
+            // even longer
+            self.keyframe_interval = (self.keyframe_interval * 2)
+                .min(self.frames.len().max(self.keyframe_interval));
+            self.keyframe_memory = self
+                .keyframes
+                .iter()
+                .map(|keyframe| screen_memory(&keyframe.screen))
+                .sum();
+        }
+    }
+}
+
+// where to find the data for a frame, so that it can be read without
+// holding the lock on the frame data
+enum Source {
+    Memory(Vec<u8>),
+    File(std::sync::Arc<std::fs::File>, u64, u32),
+}
+
+impl Source {
+    fn read(self) -> Vec<u8> {
+        match self {
+            Self::Memory(data) => data,
+            Self::File(file, offset, len) => {
+                let mut data = vec![0; usize::try_from(len).unwrap()];
+                // if the file was modified out from under us, there's not
+                // much we can do other than show nothing
+                if file.read_exact_at(&mut data, offset).is_err() {
+                    data.clear();
+                }
+                data
+            }
+        }
+    }
+}
+
+async fn read_sources(sources: Vec<Source>) -> Vec<Vec<u8>> {
+    if sources
+        .iter()
+        .all(|source| matches!(source, Source::Memory(_)))
+    {
+        return sources.into_iter().map(Source::read).collect();
+    }
+    tokio::task::spawn_blocking(move || {
+        sources.into_iter().map(Source::read).collect()
+    })
+    .await
+    // reading frames never panics, so this can never fail
+    .unwrap()
+}
+
+// replays the frames starting at `start` on top of `parser` (up to `end`,
+// or the last loaded frame), calling `f` with the screen after each frame.
+// this uses its own parser rather than the replay cursor, and only holds
+// the lock while collecting each batch of frames, so that it doesn't get in
+// the way of playback.
+async fn replay(
+    frames: &std::sync::Arc<tokio::sync::Mutex<FrameData>>,
+    mut parser: vt100::Parser,
+    mut start: usize,
+    end: Option<usize>,
+    mut f: impl FnMut(usize, &vt100::Screen) -> bool,
+) -> Option<usize> {
+    loop {
+        let data = frames.clone().lock_owned().await;
+        let count = end.unwrap_or(usize::MAX).min(data.count());
+        let batch_end = (start + SEARCH_BATCH_SIZE).min(count);
+        if start >= batch_end {
+            return None;
+        }
+        let sources = data.sources(start..batch_end);
+        drop(data);
+        for (i, data) in (start..).zip(read_sources(sources).await) {
+            ttyrec_bin::resize::process(&mut parser, &data);
+            if f(i, parser.screen()) {
+                return Some(i);
+            }
+        }
+        start = batch_end;
+        tokio::task::yield_now().await;
+    }
+}
+
+fn restore(keyframe: &crate::index::IndexedKeyframe) -> vt100::Parser {
+    let (rows, cols) = keyframe.size;
+    let mut parser = vt100::Parser::new(rows, cols, 0);
+    if keyframe.alternate_screen {
+        parser.process(b"\x1b[?1049h");
+    }
+    parser.process(&keyframe.state);
+    parser
+}
+
+/// Saves `index` for the file at `path`.
+///
+/// Serialized keyframes only contain the visible state of the screen (not
+/// things like scroll regions or saved cursor positions), so before saving,
+/// the file is replayed to check that each keyframe restored from the index
+/// reaches the same state as the original by the time the next keyframe
+/// starts. Keyframes which don't are left out of the saved index.
+pub fn save_index(
+    path: &std::path::Path,
+    mut index: crate::index::Index,
+) -> anyhow::Result<crate::index::Index> {
+    let file = std::fs::File::open(path)?;
+    let (rows, cols) = index.initial_size;
+    let mut parser = vt100::Parser::new(rows, cols, 0);
+    let mut restored: Option<(usize, vt100::Parser)> = None;
+    let mut portable = vec![true; index.keyframes.len()];
+    let matches = |restored: &vt100::Parser, parser: &vt100::Parser| {
+        restored.screen().size() == parser.screen().size()
+            && restored.screen().state_formatted()
+                == parser.screen().state_formatted()
+    };
+
+    let mut keyframes = index.keyframes.iter().enumerate().peekable();
+    for (i, frame) in index.frames.iter().enumerate() {
+        while let Some((k, keyframe)) =
+            keyframes.next_if(|(_, keyframe)| keyframe.next == i)
+        {
+            if let Some((prev, restored)) = &restored {
+                portable[*prev] = matches(restored, &parser);
+            }
+            restored = Some((k, restore(keyframe)));
+        }
+
+        let mut data = vec![0; usize::try_from(frame.len)?];
+        file.read_exact_at(
+            &mut data,
+            frame.offset + crate::index::FRAME_HEADER_LEN,
+        )?;
+        ttyrec_bin::resize::process(&mut parser, &data);
+        if let Some((_, restored)) = &mut restored {
+            ttyrec_bin::resize::process(restored, &data);
+        }
+    }
+    if let Some((prev, restored)) = &restored {
+        portable[*prev] = matches(restored, &parser);
+    }
+
+    let mut portable = portable.into_iter();
+    index.keyframes.retain(|_| portable.next().unwrap());
+    crate::index::save(path, &index)?;
+    Ok(index)
+}
+
+fn screen_memory(screen: &vt100::Screen) -> usize {
+    let (rows, cols) = screen.size();
+    // a screen holds both the normal and the alternate grid
+    usize::from(rows)
+        * usize::from(cols)
+        * std::mem::size_of::<vt100::Cell>()
+        * 2
+}
+
+// whether the parser would be left in the middle of an escape sequence or a
+// multibyte character after processing data
+fn ends_in_escape(data: &[u8]) -> bool {
+    let tail = data.iter().rev().take(4).copied().collect::<Vec<_>>();
+    if let Some(pos) = tail.iter().position(|&c| c >= 0b1100_0000) {
+        let expected = match tail[pos] {
+            0b1100_0000..=0b1101_1111 => 2,
+            0b1110_0000..=0b1110_1111 => 3,
+            _ => 4,
+        };
+        if tail[..pos]
+            .iter()
+            .all(|&c| (0b1000_0000..0b1100_0000).contains(&c))
+            && pos + 1 < expected
+        {
+            return true;
+        }
+    }
+
+    let Some(esc) = data.iter().rposition(|&c| c == 0x1b) else {
+        return false;
+    };
+    match &data[esc + 1..] {
+        [] | [b' '..=b'/'] => true,
+        [b'[', rest @ ..] => !rest.iter().any(|c| (0x40..=0x7e).contains(c)),
+        // a string terminator (\e\\) would have been found as the last
+        // escape, so reaching here means the string is still unterminated
+        // unless it ended with a bell
+        [b']' | b'P' | b'_' | b'^', rest @ ..] => !rest.contains(&0x07),
+        _ => false,
+    }
+}
+
+const FOLLOW_POLL_INTERVAL: std::time::Duration =
+    std::time::Duration::from_millis(100);
+
+// the number of frames to replay at a time when searching, before giving
+// playback a chance to use the frame data again
+const SEARCH_BATCH_SIZE: usize = 1000;
+
+/// Finds every point in the recording where `query` appears on the screen
+/// (out of the frames loaded so far), and sends their times as
+/// [`crate::event::Event::SearchHits`].
+pub fn find_all(
+    frames: std::sync::Arc<tokio::sync::Mutex<FrameData>>,
+    query: String,
+    event_w: tokio::sync::mpsc::UnboundedSender<crate::event::Event>,
+) {
+    tokio::task::spawn(async move {
+        let (next, parser) = frames.lock().await.replay_start(0, false);
+        let mut matches = vec![];
+        let mut prev_matched = false;
+        replay(&frames, parser, next, None, |i, screen| {
+            // only count the frame where the match first appears, not
+            // every frame where it stays on the screen
+            let matched = screen.contents().contains(&query);
+            if matched && !prev_matched {
+                matches.push(i);
+            }
+            prev_matched = matched;
+            false
+        })
+        .await;
+        let data = frames.lock().await;
+        let hits = matches.into_iter().map(|i| data.frame_time(i)).collect();
+        drop(data);
+        event_w
+            .send(crate::event::Event::SearchHits(query, hits))
+            // event_w is never closed, so this can never fail
+            .unwrap();
+    });
+}
+
+pub struct Timing {
+    clamp: Option<std::time::Duration>,
+    offset: Option<std::time::Duration>,
+    prev_delay: std::time::Duration,
+    clamped_amount: std::time::Duration,
+}
+
+impl Timing {
+    pub fn new(clamp: Option<u64>) -> Self {
+        Self {
+            clamp: clamp.map(std::time::Duration::from_millis),
+            offset: None,
+            prev_delay: std::time::Duration::from_secs(0),
+            clamped_amount: std::time::Duration::from_secs(0),
+        }
+    }
+
+    /// Converts the timestamp of the next frame in the file into the time
+    /// since the start of playback at which it should be shown.
+    pub fn delay(
+        &mut self,
+        time: std::time::Duration,
+    ) -> std::time::Duration {
+        let offset = *self.offset.get_or_insert(time);
+        let mut delay = time
+            .saturating_sub(offset)
+            .saturating_sub(self.clamped_amount);
+        if let Some(clamp) = self.clamp {
+            let clamped_delay = delay.min(self.prev_delay + clamp);
+            if clamped_delay < delay {
+                self.clamped_amount += delay.saturating_sub(clamped_delay);
+                delay = clamped_delay;
+            }
+        }
+        self.prev_delay = delay;
+        delay
+    }
 }
 
 pub fn load_from_file(
     frames: std::sync::Arc<tokio::sync::Mutex<FrameData>>,
     fh: tokio::fs::File,
+    path: std::path::PathBuf,
     event_w: tokio::sync::mpsc::UnboundedSender<crate::event::Event>,
     clamp: Option<u64>,
+    use_index: bool,
+    follow: bool,
 ) {
-    let clamp = clamp.map(std::time::Duration::from_millis);
     tokio::task::spawn(async move {
-        let mut reader = ttyrec::Reader::new(fh);
-        let size = terminal_size::terminal_size().map_or(
-            (24, 80),
-            |(terminal_size::Width(w), terminal_size::Height(h))| (h, w),
-        );
-        let mut parser = vt100::Parser::new(size.0, size.1, 0);
-        let mut prev_delay = std::time::Duration::from_secs(0);
-        let mut clamped_amount = std::time::Duration::from_secs(0);
-        while let Ok(frame) = reader.read_frame().await {
-            let mut delay = reader.offset().map_or_else(
-                || std::time::Duration::from_secs(0),
-                |offset| frame.time - offset - clamped_amount,
-            );
-            if let Some(clamp) = clamp {
-                let clamped_delay = delay.min(prev_delay + clamp);
-                if clamped_delay < delay {
-                    clamped_amount += delay - clamped_delay;
-                    delay = clamped_delay;
-                }
-            }
-            parser.process(&frame.data);
-            let mut frames = frames.clone().lock_owned().await;
-            frames.add_frame(Frame::new(parser.screen().clone(), delay));
+        let mut timing = Timing::new(clamp);
+
+        let index = if use_index {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || crate::index::load(&path))
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .flatten()
+        } else {
+            None
+        };
+        let mut frames_guard = frames.clone().lock_owned().await;
+        if let Some(index) = index.filter(|index| {
+            index.sized || index.initial_size == frames_guard.initial_size()
+        }) {
+            frames_guard.load_index(&index, fh.into_std().await, &mut timing);
+            frames_guard.done_reading();
+            let progress = frames_guard.progress();
+            let commands = frames_guard.commands().to_vec();
+            drop(frames_guard);
+            event_w
+                .send(crate::event::Event::Commands(commands))
+                // event_w is never closed, so this can never fail
+                .unwrap();
             event_w
-                .send(crate::event::Event::FrameLoaded(Some(frames.count())))
+                .send(crate::event::Event::FrameLoaded(Some(progress)))
                 // event_w is never closed, so this can never fail
                 .unwrap();
-            prev_delay = delay;
+            event_w
+                .send(crate::event::Event::FrameLoaded(None))
+                // event_w is never closed, so this can never fail
+                .unwrap();
+            return;
+        }
+        drop(frames_guard);
+
+        read_frames(&frames, fh, &event_w, &mut timing, follow).await;
+        let mut frames = frames.lock_owned().await;
+        frames.done_reading();
+        let index = use_index.then(|| frames.to_index());
+        drop(frames);
+        event_w
+            .send(crate::event::Event::FrameLoaded(None))
+            // event_w is never closed, so this can never fail
+            .unwrap();
+
+        if let Some(index) = index {
+            // the index is only an optimization, so failing to save it
+            // isn't worth interrupting playback over
+            let _ =
+                tokio::task::spawn_blocking(move || save_index(&path, index))
+                    .await;
         }
+    });
+}
+
+/// Loads frames from a stream which can't be indexed, such as a broadcast
+/// from `ttyrec --listen` or a compressed file.
+pub fn load_from_stream(
+    frames: std::sync::Arc<tokio::sync::Mutex<FrameData>>,
+    stream: ttyrec_bin::broadcast::Stream,
+    event_w: tokio::sync::mpsc::UnboundedSender<crate::event::Event>,
+    clamp: Option<u64>,
+) {
+    tokio::task::spawn(async move {
+        let mut timing = Timing::new(clamp);
+        read_frames(&frames, stream, &event_w, &mut timing, false).await;
+        frames.lock_owned().await.done_reading();
+        event_w
+            .send(crate::event::Event::FrameLoaded(None))
+            // event_w is never closed, so this can never fail
+            .unwrap();
+    });
+}
+
+/// Loads frames from a typescript recorded by `script`, along with its
+/// timing file.
+pub fn load_from_typescript(
+    frames: std::sync::Arc<tokio::sync::Mutex<FrameData>>,
+    log: ttyrec_bin::broadcast::Stream,
+    timing_fh: tokio::fs::File,
+    event_w: tokio::sync::mpsc::UnboundedSender<crate::event::Event>,
+    clamp: Option<u64>,
+) {
+    tokio::task::spawn(async move {
+        let mut timing = Timing::new(clamp);
+        let reader = ttyrec_bin::format::Reader::typescript(log, timing_fh);
+        add_frames(&frames, reader, &event_w, &mut timing, false).await;
         frames.lock_owned().await.done_reading();
         event_w
             .send(crate::event::Event::FrameLoaded(None))
@@ -156,3 +888,168 @@ pub fn load_from_file(
             .unwrap();
     });
 }
+
+async fn read_frames<T: tokio::io::AsyncRead + std::marker::Unpin + Send>(
+    frames: &std::sync::Arc<tokio::sync::Mutex<FrameData>>,
+    input: T,
+    event_w: &tokio::sync::mpsc::UnboundedSender<crate::event::Event>,
+    timing: &mut Timing,
+    follow: bool,
+) {
+    let mut input = tokio::io::BufReader::new(input);
+    let format = loop {
+        match ttyrec_bin::format::Format::detect(&mut input).await {
+            Ok(Some(format)) => break format,
+            Ok(None) if follow => {
+                tokio::time::sleep(FOLLOW_POLL_INTERVAL).await;
+            }
+            Ok(None) | Err(_) => return,
+        }
+    };
+    let reader = ttyrec_bin::format::Reader::new(input, format);
+    add_frames(frames, reader, event_w, timing, follow).await;
+}
+
+async fn add_frames<T: tokio::io::AsyncRead + std::marker::Unpin + Send>(
+    frames: &std::sync::Arc<tokio::sync::Mutex<FrameData>>,
+    mut reader: ttyrec_bin::format::Reader<T>,
+    event_w: &tokio::sync::mpsc::UnboundedSender<crate::event::Event>,
+    timing: &mut Timing,
+    follow: bool,
+) {
+    let size = frames.lock().await.initial_size();
+    let mut parser = vt100::Parser::new_with_callbacks(
+        size.0,
+        size.1,
+        0,
+        crate::commands::Markers::default(),
+    );
+    let mut detector = crate::commands::Detector::new();
+    loop {
+        let frame = match reader.read_frame().await {
+            Ok(Some(frame)) => frame,
+            Ok(None) if follow => {
+                // the reader keeps any partial frame it has already
+                // read, so we can just try again once more data has
+                // been written
+                tokio::time::sleep(FOLLOW_POLL_INTERVAL).await;
+                continue;
+            }
+            Ok(None) | Err(_) => break,
+        };
+        let delay = timing.delay(frame.time);
+        ttyrec_bin::resize::process(&mut parser, &frame.data);
+        let mut frames = frames.clone().lock_owned().await;
+        frames.add_frame(frame, delay, parser.screen());
+        let progress = frames.progress();
+        let markers = parser.callbacks_mut().take();
+        let commands = detector
+            .frame(
+                progress.count - 1,
+                progress.duration,
+                markers,
+                parser.screen(),
+            )
+            .then(|| detector.commands().to_vec());
+        if let Some(commands) = &commands {
+            frames.set_commands(commands.clone());
+        }
+        drop(frames);
+        if let Some(commands) = commands {
+            event_w
+                .send(crate::event::Event::Commands(commands))
+                // event_w is never closed, so this can never fail
+                .unwrap();
+        }
+        event_w
+            .send(crate::event::Event::FrameLoaded(Some(progress)))
+            // event_w is never closed, so this can never fail
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // a recording with one frame per second, each containing one of `data`
+    fn frame_data(data: &[&str]) -> super::FrameData {
+        let mut frames = super::FrameData::new((24, 80), 2, None);
+        let mut parser = vt100::Parser::new(24, 80, 0);
+        for (secs, data) in (0..).zip(data) {
+            let time = std::time::Duration::from_secs(secs);
+            parser.process(data.as_bytes());
+            frames.add_frame(
+                ttyrec::Frame {
+                    time,
+                    data: data.as_bytes().to_vec(),
+                },
+                time,
+                parser.screen(),
+            );
+        }
+        frames
+    }
+
+    #[test]
+    fn times_resolve_before_loading_finishes() {
+        let mut frames = frame_data(&["a", "b", "c"]);
+        let position = crate::time::Position::Time(
+            std::time::Duration::from_millis(1500),
+        );
+        assert!(frames.position_loaded(position));
+        assert_eq!(frames.frame_at_delay(frames.position_delay(position)), 1);
+
+        let position =
+            crate::time::Position::Time(std::time::Duration::from_secs(5));
+        assert!(!frames.position_loaded(position));
+        frames.done_reading();
+        assert!(frames.position_loaded(position));
+        assert_eq!(frames.frame_at_delay(frames.position_delay(position)), 2);
+    }
+
+    #[test]
+    fn percentages_need_the_whole_recording() {
+        let mut frames = frame_data(&["a", "b", "c", "d", "e"]);
+        let position = crate::time::Position::Percent(50.0);
+        assert!(!frames.position_loaded(position));
+        frames.done_reading();
+        assert!(frames.position_loaded(position));
+        assert_eq!(
+            frames.position_delay(position),
+            std::time::Duration::from_secs(2)
+        );
+        assert_eq!(frames.frame_at_delay(frames.position_delay(position)), 2);
+        assert_eq!(
+            frames.frame_at_delay(
+                frames.position_delay(crate::time::Position::Percent(100.0))
+            ),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn get_and_search() {
+        let frames =
+            std::sync::Arc::new(tokio::sync::Mutex::new(frame_data(&[
+                "a", "b", "c", "\x1b[Hx", "d",
+            ])));
+        // out of order, so that both the keyframes and the cursor are used
+        for (i, contents) in [(2, "abc"), (4, "xdc"), (1, "ab"), (3, "xbc")] {
+            let frame = super::FrameData::get(&frames, i).await.unwrap();
+            assert_eq!(frame.into_screen().contents(), contents);
+        }
+        assert!(super::FrameData::get(&frames, 5).await.is_none());
+
+        let search = |start, query, backwards| {
+            let frames = frames.clone();
+            async move {
+                super::FrameData::search(&frames, start, query, backwards)
+                    .await
+            }
+        };
+        assert_eq!(search(0, "c", false).await, Some(2));
+        assert_eq!(search(3, "a", false).await, None);
+        assert_eq!(search(5, "a", true).await, Some(2));
+        assert_eq!(search(5, "x", true).await, Some(3));
+        assert_eq!(search(2, "x", true).await, None);
+    }
+}
@@ -0,0 +1,342 @@
+use std::io::Read as _;
+use std::os::unix::ffi::OsStrExt as _;
+use std::os::unix::fs::FileExt as _;
+
+const MAGIC: &[u8; 8] = b"TTYRIDX\0";
+const VERSION: u32 = 2;
+pub const FRAME_HEADER_LEN: u64 = 12;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Command {
+    /// Read the whole file and save an index for it
+    Build,
+    /// Check that the saved index matches the file
+    Verify,
+    /// Remove saved indexes for files which no longer exist or have changed
+    Prune,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IndexedFrame {
+    pub offset: u64,
+    pub len: u32,
+    pub time: std::time::Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexedKeyframe {
+    pub next: usize,
+    pub size: (u16, u16),
+    pub alternate_screen: bool,
+    pub state: Vec<u8>,
+}
+
+/// Frame boundaries and saved screen states for a single recording, which
+/// allow the recording to be opened without parsing it from the start.
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub initial_size: (u16, u16),
+    pub sized: bool,
+    pub frames: Vec<IndexedFrame>,
+    pub keyframes: Vec<IndexedKeyframe>,
+    pub commands: Vec<crate::commands::Command>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Source {
+    path: std::path::PathBuf,
+    len: u64,
+    mtime: std::time::Duration,
+}
+
+impl Source {
+    fn new(file: &std::path::Path) -> std::io::Result<Self> {
+        let path = std::fs::canonicalize(file)?;
+        let metadata = std::fs::metadata(&path)?;
+        Ok(Self {
+            path,
+            len: metadata.len(),
+            mtime: metadata
+                .modified()?
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default(),
+        })
+    }
+
+    fn cache_file(&self) -> Option<std::path::PathBuf> {
+        use std::hash::{Hash as _, Hasher as _};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.path.hash(&mut hasher);
+        cache_dir().map(|dir| dir.join(format!("{:016x}", hasher.finish())))
+    }
+}
+
+fn cache_dir() -> Option<std::path::PathBuf> {
+    directories::ProjectDirs::from("", "", "ttyrec-bin")
+        .map(|dirs| dirs.cache_dir().join("index"))
+}
+
+/// Returns the saved index for `file`, if one exists and `file` hasn't
+/// changed since it was saved.
+pub fn load(file: &std::path::Path) -> anyhow::Result<Option<Index>> {
+    let source = Source::new(file)?;
+    let Some(cache_file) = source.cache_file() else {
+        return Ok(None);
+    };
+    let buf = match std::fs::read(cache_file) {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut reader = Reader { buf: &buf };
+    if reader.source()? != source {
+        return Ok(None);
+    }
+    reader.index().map(Some)
+}
+
+pub fn save(file: &std::path::Path, index: &Index) -> anyhow::Result<()> {
+    let source = Source::new(file)?;
+    let Some(cache_file) = source.cache_file() else {
+        return Ok(());
+    };
+    // cache_file always has a parent, since it was created by join
+    let dir = cache_file.parent().unwrap();
+    std::fs::create_dir_all(dir)?;
+
+    let mut buf = vec![];
+    buf.extend(MAGIC);
+    buf.extend(VERSION.to_le_bytes());
+    write_bytes(&mut buf, source.path.as_os_str().as_bytes());
+    buf.extend(source.len.to_le_bytes());
+    write_duration(&mut buf, source.mtime);
+
+    buf.extend(index.initial_size.0.to_le_bytes());
+    buf.extend(index.initial_size.1.to_le_bytes());
+    buf.push(u8::from(index.sized));
+    buf.extend(u64::try_from(index.frames.len())?.to_le_bytes());
+    for frame in &index.frames {
+        buf.extend(frame.offset.to_le_bytes());
+        buf.extend(frame.len.to_le_bytes());
+        write_duration(&mut buf, frame.time);
+    }
+    buf.extend(u64::try_from(index.keyframes.len())?.to_le_bytes());
+    for keyframe in &index.keyframes {
+        buf.extend(u64::try_from(keyframe.next)?.to_le_bytes());
+        buf.extend(keyframe.size.0.to_le_bytes());
+        buf.extend(keyframe.size.1.to_le_bytes());
+        buf.push(u8::from(keyframe.alternate_screen));
+        write_bytes(&mut buf, &keyframe.state);
+    }
+    buf.extend(u64::try_from(index.commands.len())?.to_le_bytes());
+    for command in &index.commands {
+        write_bytes(&mut buf, command.name.as_bytes());
+        buf.extend(u64::try_from(command.start)?.to_le_bytes());
+        write_duration(&mut buf, command.time);
+        // usize::MAX is never a valid frame index, so it can mean the
+        // command never finished
+        buf.extend(
+            u64::try_from(command.end.unwrap_or(usize::MAX))?.to_le_bytes(),
+        );
+        buf.push(u8::from(command.exit.is_some()));
+        buf.extend(command.exit.unwrap_or(0).to_le_bytes());
+    }
+
+    // write to a temporary file first so that a concurrent reader never
+    // sees a partially written index
+    let tmp = dir.join(format!(".tmp.{}", std::process::id()));
+    std::fs::write(&tmp, &buf)?;
+    std::fs::rename(&tmp, cache_file)?;
+    Ok(())
+}
+
+/// Checks that the saved index for `file` is up to date, and that every
+/// frame it describes matches the frame actually found in the file.
+pub fn verify(file: &std::path::Path) -> anyhow::Result<Index> {
+    let Some(index) = load(file)? else {
+        anyhow::bail!(
+            "no up to date index found for {}",
+            file.to_string_lossy()
+        );
+    };
+
+    let fh = std::fs::File::open(file)?;
+    let mut expected_offset = 0;
+    for (i, frame) in index.frames.iter().enumerate() {
+        let mut header = [0; 12];
+        fh.read_exact_at(&mut header, frame.offset)?;
+        let secs = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let micros = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let len = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        let time = std::time::Duration::from_secs(u64::from(secs))
+            + std::time::Duration::from_micros(u64::from(micros));
+        if frame.offset != expected_offset
+            || frame.len != len
+            || frame.time != time
+        {
+            anyhow::bail!("index doesn't match file at frame {}", i + 1);
+        }
+        expected_offset = frame.offset + FRAME_HEADER_LEN + u64::from(len);
+    }
+    for keyframe in &index.keyframes {
+        if keyframe.next > index.frames.len() {
+            anyhow::bail!("index contains a keyframe past the end of file");
+        }
+    }
+
+    Ok(index)
+}
+
+/// Removes saved indexes which no longer correspond to an existing file,
+/// returning the number of indexes removed.
+pub fn prune() -> anyhow::Result<usize> {
+    let Some(dir) = cache_dir() else {
+        return Ok(0);
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut pruned = 0;
+    for entry in entries {
+        let path = entry?.path();
+        let mut header = vec![];
+        std::fs::File::open(&path)?
+            .take(64 * 1024)
+            .read_to_end(&mut header)?;
+        let valid = Reader { buf: &header }.source().is_ok_and(|source| {
+            Source::new(&source.path).is_ok_and(|current| {
+                current == source
+                    && current.cache_file().as_deref() == Some(path.as_ref())
+            })
+        });
+        if !valid {
+            std::fs::remove_file(&path)?;
+            pruned += 1;
+        }
+    }
+    Ok(pruned)
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend(u64::try_from(bytes.len()).unwrap().to_le_bytes());
+    buf.extend(bytes);
+}
+
+fn write_duration(buf: &mut Vec<u8>, duration: std::time::Duration) {
+    buf.extend(duration.as_secs().to_le_bytes());
+    buf.extend(duration.subsec_nanos().to_le_bytes());
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn source(&mut self) -> anyhow::Result<Source> {
+        if self.take(MAGIC.len())? != MAGIC {
+            anyhow::bail!("not a ttyrec index file");
+        }
+        if self.u32()? != VERSION {
+            anyhow::bail!("unsupported ttyrec index version");
+        }
+        let path = std::path::PathBuf::from(
+            std::ffi::OsStr::from_bytes(self.bytes()?).to_owned(),
+        );
+        Ok(Source {
+            path,
+            len: self.u64()?,
+            mtime: self.duration()?,
+        })
+    }
+
+    fn index(&mut self) -> anyhow::Result<Index> {
+        let initial_size = (self.u16()?, self.u16()?);
+        let sized = self.u8()? != 0;
+        let frames = (0..self.u64()?)
+            .map(|_| {
+                Ok(IndexedFrame {
+                    offset: self.u64()?,
+                    len: self.u32()?,
+                    time: self.duration()?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let keyframes = (0..self.u64()?)
+            .map(|_| {
+                Ok(IndexedKeyframe {
+                    next: usize::try_from(self.u64()?)?,
+                    size: (self.u16()?, self.u16()?),
+                    alternate_screen: self.u8()? != 0,
+                    state: self.bytes()?.to_vec(),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let commands = (0..self.u64()?)
+            .map(|_| {
+                Ok(crate::commands::Command {
+                    name: String::from_utf8(self.bytes()?.to_vec())?,
+                    start: usize::try_from(self.u64()?)?,
+                    time: self.duration()?,
+                    end: Some(usize::try_from(self.u64()?)?)
+                        .filter(|&end| end != usize::MAX),
+                    exit: {
+                        let has_exit = self.u8()? != 0;
+                        let exit = self.i32()?;
+                        has_exit.then_some(exit)
+                    },
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Index {
+            initial_size,
+            sized,
+            frames,
+            keyframes,
+            commands,
+        })
+    }
+
+    fn take(&mut self, n: usize) -> anyhow::Result<&'a [u8]> {
+        if self.buf.len() < n {
+            anyhow::bail!("truncated ttyrec index file");
+        }
+        let (bytes, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn bytes(&mut self) -> anyhow::Result<&'a [u8]> {
+        let len = usize::try_from(self.u64()?)?;
+        self.take(len)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn duration(&mut self) -> anyhow::Result<std::time::Duration> {
+        Ok(std::time::Duration::new(self.u64()?, self.u32()?))
+    }
+}
@@ -1,13 +1,48 @@
+const SHORT_SEEK: std::time::Duration = std::time::Duration::from_secs(5);
+const MEDIUM_SEEK: std::time::Duration = std::time::Duration::from_secs(30);
+const LONG_SEEK: std::time::Duration = std::time::Duration::from_mins(5);
+// how long to wait after an escape for the rest of a mouse report. textmode
+// will already have read the whole report by the time it returns the
+// escape, so this only delays escapes which were typed on their own.
+const ESCAPE_TIMEOUT: std::time::Duration =
+    std::time::Duration::from_millis(50);
+
+type KeyResult = textmode::Result<Option<textmode::Key>>;
+
+enum Input {
+    Key(textmode::Key),
+    Mouse(crate::mouse::Mouse),
+}
+
 pub fn spawn_thread(
     event_w: tokio::sync::mpsc::UnboundedSender<crate::event::Event>,
     mut input: textmode::blocking::Input,
+    keymap: crate::keymap::Keymap,
 ) {
+    let (key_w, key_r) = std::sync::mpsc::channel();
+    std::thread::spawn(move || loop {
+        let key = input.read_key();
+        let done = !matches!(key, Ok(Some(_)));
+        if key_w.send(key).is_err() || done {
+            break;
+        }
+    });
     std::thread::spawn(move || {
         let mut search: Option<String> = None;
         let mut prev_search = None;
+        let mut bookmark_name: Option<String> = None;
+        let mut goto: Option<String> = None;
+        let mut pending = std::collections::VecDeque::new();
         loop {
-            let key = match input.read_key() {
-                Ok(Some(key)) => key,
+            let key = match read_input(&key_r, &mut pending) {
+                Ok(Some(Input::Key(key))) => key,
+                Ok(Some(Input::Mouse(mouse))) => {
+                    event_w
+                        .send(crate::event::Event::Mouse(mouse))
+                        // event_w is never closed, so this can never fail
+                        .unwrap();
+                    continue;
+                }
                 Ok(None) => break,
                 Err(e) => {
                     event_w
@@ -17,7 +52,62 @@ pub fn spawn_thread(
                     break;
                 }
             };
-            if let Some(ref mut search_contents) = search {
+            if let Some(ref mut position) = goto {
+                let event = match key {
+                    textmode::Key::Char(c) => {
+                        position.push(c);
+                        crate::event::Event::GotoPrompt(position.clone())
+                    }
+                    textmode::Key::Backspace => {
+                        position.pop();
+                        crate::event::Event::GotoPrompt(position.clone())
+                    }
+                    textmode::Key::Ctrl(b'm') => {
+                        // keep the prompt open until something valid is
+                        // entered
+                        let Ok(position) =
+                            crate::time::parse_position(position)
+                        else {
+                            continue;
+                        };
+                        goto = None;
+                        crate::event::Event::RunGoto(position)
+                    }
+                    textmode::Key::Escape => {
+                        goto = None;
+                        crate::event::Event::CancelGoto
+                    }
+                    _ => continue,
+                };
+                // event_w is never closed, so this can never fail
+                event_w.send(event).unwrap();
+            } else if let Some(ref mut name) = bookmark_name {
+                let event = match key {
+                    textmode::Key::Char(c) => {
+                        name.push(c);
+                        crate::event::Event::BookmarkPrompt(name.clone())
+                    }
+                    textmode::Key::Backspace => {
+                        name.pop();
+                        crate::event::Event::BookmarkPrompt(name.clone())
+                    }
+                    textmode::Key::Ctrl(b'm') => {
+                        let name = bookmark_name.take().unwrap_or_default();
+                        crate::event::Event::Bookmark(
+                            crate::event::BookmarkAction::Add(name),
+                        )
+                    }
+                    textmode::Key::Escape => {
+                        bookmark_name = None;
+                        crate::event::Event::Bookmark(
+                            crate::event::BookmarkAction::Cancel,
+                        )
+                    }
+                    _ => continue,
+                };
+                // event_w is never closed, so this can never fail
+                event_w.send(event).unwrap();
+            } else if let Some(ref mut search_contents) = search {
                 match key {
                     textmode::Key::Char(c) => {
                         search_contents.push(c);
@@ -58,59 +148,65 @@ pub fn spawn_thread(
                     _ => {}
                 }
             } else {
-                let event = match key {
-                    textmode::Key::Char('0') => {
-                        crate::event::Event::TimerAction(
-                            crate::event::TimerAction::FirstFrame,
-                        )
+                let Some(action) = keymap.action(key) else {
+                    continue;
+                };
+                let event = match action {
+                    crate::keymap::Action::Quit => crate::event::Event::Quit,
+                    crate::keymap::Action::Pause => {
+                        timer_action(crate::event::TimerAction::Pause)
                     }
-                    textmode::Key::Char('$') => {
-                        crate::event::Event::TimerAction(
-                            crate::event::TimerAction::LastFrame,
-                        )
+                    crate::keymap::Action::ToggleUi => {
+                        crate::event::Event::ToggleUi
                     }
-                    textmode::Key::Char('l') => {
-                        crate::event::Event::TimerAction(
-                            crate::event::TimerAction::NextFrame,
-                        )
+                    crate::keymap::Action::PreviousFrame => {
+                        timer_action(crate::event::TimerAction::PreviousFrame)
                     }
-                    textmode::Key::Char('h') => {
-                        crate::event::Event::TimerAction(
-                            crate::event::TimerAction::PreviousFrame,
-                        )
+                    crate::keymap::Action::NextFrame => {
+                        timer_action(crate::event::TimerAction::NextFrame)
                     }
-                    textmode::Key::Char('q') => crate::event::Event::Quit,
-                    textmode::Key::Char(' ') => {
-                        crate::event::Event::TimerAction(
-                            crate::event::TimerAction::Pause,
-                        )
+                    crate::keymap::Action::FirstFrame => {
+                        timer_action(crate::event::TimerAction::FirstFrame)
                     }
-                    textmode::Key::Ctrl(b'i') => {
-                        crate::event::Event::ToggleUi
+                    crate::keymap::Action::LastFrame => {
+                        timer_action(crate::event::TimerAction::LastFrame)
                     }
-                    textmode::Key::Char('?') => {
-                        crate::event::Event::ToggleHelp
+                    crate::keymap::Action::BackShort => {
+                        seek(crate::event::Seek::Backward, SHORT_SEEK)
                     }
-                    textmode::Key::Char('+') => {
-                        crate::event::Event::TimerAction(
-                            crate::event::TimerAction::SpeedUp,
-                        )
+                    crate::keymap::Action::ForwardShort => {
+                        seek(crate::event::Seek::Forward, SHORT_SEEK)
                     }
-                    textmode::Key::Char('-') => {
-                        crate::event::Event::TimerAction(
-                            crate::event::TimerAction::SlowDown,
-                        )
+                    crate::keymap::Action::BackMedium => {
+                        seek(crate::event::Seek::Backward, MEDIUM_SEEK)
                     }
-                    textmode::Key::Char('=') => {
-                        crate::event::Event::TimerAction(
-                            crate::event::TimerAction::DefaultSpeed,
-                        )
+                    crate::keymap::Action::ForwardMedium => {
+                        seek(crate::event::Seek::Forward, MEDIUM_SEEK)
+                    }
+                    crate::keymap::Action::BackLong => {
+                        seek(crate::event::Seek::Backward, LONG_SEEK)
+                    }
+                    crate::keymap::Action::ForwardLong => {
+                        seek(crate::event::Seek::Forward, LONG_SEEK)
                     }
-                    textmode::Key::Char('/') => {
+                    crate::keymap::Action::GoTo => {
+                        goto = Some(String::new());
+                        crate::event::Event::GotoPrompt(String::new())
+                    }
+                    crate::keymap::Action::SpeedUp => {
+                        timer_action(crate::event::TimerAction::SpeedUp)
+                    }
+                    crate::keymap::Action::SlowDown => {
+                        timer_action(crate::event::TimerAction::SlowDown)
+                    }
+                    crate::keymap::Action::NormalSpeed => {
+                        timer_action(crate::event::TimerAction::DefaultSpeed)
+                    }
+                    crate::keymap::Action::Search => {
                         search = Some(String::new());
                         crate::event::Event::ActiveSearch(String::new())
                     }
-                    textmode::Key::Char('n') => {
+                    crate::keymap::Action::NextMatch => {
                         if let Some(ref search) = prev_search {
                             crate::event::Event::RunSearch(
                                 search.clone(),
@@ -120,7 +216,7 @@ pub fn spawn_thread(
                             continue;
                         }
                     }
-                    textmode::Key::Char('p') => {
+                    crate::keymap::Action::PreviousMatch => {
                         if let Some(ref search) = prev_search {
                             crate::event::Event::RunSearch(
                                 search.clone(),
@@ -130,7 +226,59 @@ pub fn spawn_thread(
                             continue;
                         }
                     }
-                    _ => continue,
+                    crate::keymap::Action::PanUp => {
+                        crate::event::Event::Pan(crate::event::Pan::Up)
+                    }
+                    crate::keymap::Action::PanDown => {
+                        crate::event::Event::Pan(crate::event::Pan::Down)
+                    }
+                    crate::keymap::Action::PanLeft => {
+                        crate::event::Event::Pan(crate::event::Pan::Left)
+                    }
+                    crate::keymap::Action::PanRight => {
+                        crate::event::Event::Pan(crate::event::Pan::Right)
+                    }
+                    crate::keymap::Action::FollowCursor => {
+                        crate::event::Event::ToggleFollowCursor
+                    }
+                    crate::keymap::Action::ToggleKeys => {
+                        crate::event::Event::ToggleKeys
+                    }
+                    crate::keymap::Action::ToggleInfo => {
+                        crate::event::Event::ToggleInfo
+                    }
+                    crate::keymap::Action::NextBookmark => {
+                        crate::event::Event::Bookmark(
+                            crate::event::BookmarkAction::Next,
+                        )
+                    }
+                    crate::keymap::Action::PreviousBookmark => {
+                        crate::event::Event::Bookmark(
+                            crate::event::BookmarkAction::Previous,
+                        )
+                    }
+                    crate::keymap::Action::AddBookmark => {
+                        bookmark_name = Some(String::new());
+                        crate::event::Event::BookmarkPrompt(String::new())
+                    }
+                    crate::keymap::Action::ToggleBookmarks => {
+                        crate::event::Event::ToggleBookmarks
+                    }
+                    crate::keymap::Action::NextCommand => timer_action(
+                        crate::event::TimerAction::JumpCommand(false),
+                    ),
+                    crate::keymap::Action::PreviousCommand => timer_action(
+                        crate::event::TimerAction::JumpCommand(true),
+                    ),
+                    crate::keymap::Action::ToggleCommands => {
+                        crate::event::Event::ToggleCommands
+                    }
+                    crate::keymap::Action::ToggleTimeline => {
+                        crate::event::Event::ToggleTimeline
+                    }
+                    crate::keymap::Action::ToggleHelp => {
+                        crate::event::Event::ToggleHelp
+                    }
                 };
                 // event_w is never closed, so this can never fail
                 event_w.send(event).unwrap();
@@ -138,3 +286,164 @@ pub fn spawn_thread(
         }
     });
 }
+
+fn timer_action(action: crate::event::TimerAction) -> crate::event::Event {
+    crate::event::Event::TimerAction(action)
+}
+
+fn seek(
+    direction: fn(std::time::Duration) -> crate::event::Seek,
+    duration: std::time::Duration,
+) -> crate::event::Event {
+    crate::event::Event::TimerAction(crate::event::TimerAction::Seek(
+        direction(duration),
+    ))
+}
+
+// with parse_single disabled, textmode returns a run of printable characters
+// as a single string, which is what allows mouse reports (which textmode
+// doesn't understand, so it returns the escape at the start of them as a
+// separate key, followed by the rest of the report as a string) to be told
+// apart from typed characters. everything else is split back up into
+// individual keys.
+fn read_input(
+    keys: &std::sync::mpsc::Receiver<KeyResult>,
+    pending: &mut std::collections::VecDeque<textmode::Key>,
+) -> textmode::Result<Option<Input>> {
+    if let Some(key) = pending.pop_front() {
+        return Ok(Some(Input::Key(key)));
+    }
+    // the reader thread only stops after sending an error or eof
+    let Ok(key) = keys.recv() else {
+        return Ok(None);
+    };
+    let Some(key) = key? else {
+        return Ok(None);
+    };
+    if matches!(key, textmode::Key::Escape) {
+        match keys.recv_timeout(ESCAPE_TIMEOUT) {
+            Ok(Ok(Some(textmode::Key::String(s)))) => {
+                if let Some((mouse, rest)) = parse_mouse(&s) {
+                    pending.extend(rest.chars().map(textmode::Key::Char));
+                    return Ok(Some(Input::Mouse(mouse)));
+                }
+                pending.push_back(textmode::Key::Escape);
+                push_key(pending, textmode::Key::String(s));
+            }
+            Ok(Ok(Some(next))) => {
+                pending.push_back(textmode::Key::Escape);
+                push_key(pending, next);
+            }
+            Ok(Ok(None)) => return Ok(None),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => pending.push_back(textmode::Key::Escape),
+        }
+    } else {
+        push_key(pending, key);
+    }
+    Ok(pending.pop_front().map(Input::Key))
+}
+
+fn push_key(
+    pending: &mut std::collections::VecDeque<textmode::Key>,
+    key: textmode::Key,
+) {
+    match key {
+        textmode::Key::String(s) => {
+            pending.extend(s.chars().map(textmode::Key::Char));
+        }
+        textmode::Key::Bytes(bytes) => {
+            pending.extend(bytes.into_iter().map(textmode::Key::Byte));
+        }
+        key => pending.push_back(key),
+    }
+}
+
+// parses a mouse report at the start of `s` (which followed an escape),
+// returning it along with whatever came after it
+fn parse_mouse(s: &str) -> Option<(crate::mouse::Mouse, &str)> {
+    if !s.starts_with("[<") {
+        return None;
+    }
+    let (report, rest) = s.split_at(s.find(['M', 'm'])? + 1);
+    Some((crate::mouse::parse(report)?, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    // reads all of the input from `keys`, with mouse reports written as
+    // `Mouse(ROW,COL)`
+    fn read(keys: Vec<textmode::Key>) -> Vec<String> {
+        let (keys_w, keys_r) = std::sync::mpsc::channel();
+        for key in keys {
+            keys_w.send(Ok(Some(key))).unwrap();
+        }
+        keys_w.send(Ok(None)).unwrap();
+        let mut pending = std::collections::VecDeque::new();
+        let mut inputs = vec![];
+        while let Some(input) =
+            super::read_input(&keys_r, &mut pending).unwrap()
+        {
+            inputs.push(match input {
+                super::Input::Key(key) => format!("{key:?}"),
+                super::Input::Mouse(mouse) => {
+                    format!("Mouse{:?}", mouse.pos)
+                }
+            });
+        }
+        inputs
+    }
+
+    #[test]
+    fn mouse_reports_after_escape() {
+        // textmode can't parse SGR mouse reports, so they arrive as an
+        // escape followed by the rest of the report
+        assert_eq!(
+            read(vec![
+                textmode::Key::Escape,
+                textmode::Key::String("[<0;5;3M".to_string()),
+                textmode::Key::Escape,
+                textmode::Key::String("[<0;5;3mab".to_string()),
+            ]),
+            vec!["Mouse(2, 4)", "Mouse(2, 4)", "Char('a')", "Char('b')"]
+        );
+    }
+
+    #[test]
+    fn escape_followed_by_other_keys() {
+        assert_eq!(
+            read(vec![
+                textmode::Key::Escape,
+                textmode::Key::String("ab".to_string()),
+                textmode::Key::Escape,
+                textmode::Key::Up,
+                textmode::Key::Escape,
+                textmode::Key::Char('x'),
+            ]),
+            vec![
+                "Escape",
+                "Char('a')",
+                "Char('b')",
+                "Escape",
+                "Up",
+                "Escape",
+                "Char('x')",
+            ]
+        );
+    }
+
+    #[test]
+    fn lone_escape() {
+        // nothing else arrives before the timeout
+        let (keys_w, keys_r) = std::sync::mpsc::channel();
+        keys_w.send(Ok(Some(textmode::Key::Escape))).unwrap();
+        let mut pending = std::collections::VecDeque::new();
+        assert!(matches!(
+            super::read_input(&keys_r, &mut pending).unwrap(),
+            Some(super::Input::Key(textmode::Key::Escape))
+        ));
+        assert!(pending.is_empty());
+        drop(keys_w);
+        assert!(super::read_input(&keys_r, &mut pending).unwrap().is_none());
+    }
+}
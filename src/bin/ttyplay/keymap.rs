@@ -0,0 +1,381 @@
+/// Something which can be done by pressing a key in the player.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Quit,
+    Pause,
+    ToggleUi,
+    PreviousFrame,
+    NextFrame,
+    FirstFrame,
+    LastFrame,
+    #[serde(rename = "back-5s")]
+    BackShort,
+    #[serde(rename = "forward-5s")]
+    ForwardShort,
+    #[serde(rename = "back-30s")]
+    BackMedium,
+    #[serde(rename = "forward-30s")]
+    ForwardMedium,
+    #[serde(rename = "back-5m")]
+    BackLong,
+    #[serde(rename = "forward-5m")]
+    ForwardLong,
+    GoTo,
+    SpeedUp,
+    SlowDown,
+    NormalSpeed,
+    Search,
+    NextMatch,
+    PreviousMatch,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    FollowCursor,
+    ToggleKeys,
+    ToggleInfo,
+    NextBookmark,
+    PreviousBookmark,
+    AddBookmark,
+    ToggleBookmarks,
+    NextCommand,
+    PreviousCommand,
+    ToggleCommands,
+    ToggleTimeline,
+    ToggleHelp,
+}
+
+// key names are the ones understood by ttyrec_bin::keys::encode
+const DEFAULTS: &[(Action, &[&str])] = &[
+    (Action::Quit, &["q"]),
+    (Action::Pause, &["Space"]),
+    (Action::ToggleUi, &["Tab"]),
+    (Action::PreviousFrame, &["h"]),
+    (Action::NextFrame, &["l"]),
+    (Action::FirstFrame, &["0"]),
+    (Action::LastFrame, &["$"]),
+    (Action::BackShort, &[","]),
+    (Action::ForwardShort, &["."]),
+    (Action::BackMedium, &["<"]),
+    (Action::ForwardMedium, &[">"]),
+    (Action::BackLong, &["PgUp"]),
+    (Action::ForwardLong, &["PgDn"]),
+    (Action::GoTo, &["g"]),
+    (Action::SpeedUp, &["+"]),
+    (Action::SlowDown, &["-"]),
+    (Action::NormalSpeed, &["="]),
+    (Action::Search, &["/"]),
+    (Action::NextMatch, &["n"]),
+    (Action::PreviousMatch, &["p"]),
+    (Action::PanUp, &["Up"]),
+    (Action::PanDown, &["Down"]),
+    (Action::PanLeft, &["Left"]),
+    (Action::PanRight, &["Right"]),
+    (Action::FollowCursor, &["f"]),
+    (Action::ToggleKeys, &["k"]),
+    (Action::ToggleInfo, &["i"]),
+    (Action::NextBookmark, &["]"]),
+    (Action::PreviousBookmark, &["["]),
+    (Action::AddBookmark, &["m"]),
+    (Action::ToggleBookmarks, &["b"]),
+    (Action::NextCommand, &["}"]),
+    (Action::PreviousCommand, &["{"]),
+    (Action::ToggleCommands, &["c"]),
+    (Action::ToggleTimeline, &["t"]),
+    (Action::ToggleHelp, &["?"]),
+];
+
+// the lines of the help overlay, where each line shows the keys for all of
+// the given actions
+const HELP: &[(&[Action], &str)] = &[
+    (&[Action::Quit], "quit"),
+    (&[Action::Pause], "pause/unpause"),
+    (&[Action::ToggleUi], "hide/show ui"),
+    (&[Action::PreviousFrame], "previous frame"),
+    (&[Action::NextFrame], "next frame"),
+    (&[Action::FirstFrame], "first frame"),
+    (&[Action::LastFrame], "last frame"),
+    (&[Action::BackShort, Action::ForwardShort], "back/fwd 5s"),
+    (&[Action::BackMedium, Action::ForwardMedium], "back/fwd 30s"),
+    (&[Action::BackLong, Action::ForwardLong], "back/fwd 5m"),
+    (&[Action::GoTo], "go to time"),
+    (&[Action::SpeedUp], "increase speed"),
+    (&[Action::SlowDown], "decrease speed"),
+    (&[Action::NormalSpeed], "normal speed"),
+    (&[Action::Search], "search"),
+    (&[Action::NextMatch], "next match"),
+    (&[Action::PreviousMatch], "previous match"),
+    (
+        &[
+            Action::PanLeft,
+            Action::PanUp,
+            Action::PanDown,
+            Action::PanRight,
+        ],
+        "pan view",
+    ),
+    (&[Action::FollowCursor], "follow cursor"),
+    (&[Action::ToggleKeys], "show/hide keys"),
+    (&[Action::ToggleInfo], "show/hide info"),
+    (&[Action::NextBookmark], "next bookmark"),
+    (&[Action::PreviousBookmark], "prev bookmark"),
+    (&[Action::AddBookmark], "add bookmark"),
+    (&[Action::ToggleBookmarks], "list bookmarks"),
+    (&[Action::NextCommand], "next command"),
+    (&[Action::PreviousCommand], "prev command"),
+    (&[Action::ToggleCommands], "list commands"),
+    (&[Action::ToggleTimeline], "show timeline"),
+    (&[Action::ToggleHelp], "hide/show help"),
+];
+
+/// The keys bound to an action in a keymap file, which can be either a
+/// single key or a list of keys.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Keys {
+    fn names(&self) -> &[String] {
+        match self {
+            Self::One(name) => std::slice::from_ref(name),
+            Self::Many(names) => names,
+        }
+    }
+}
+
+/// Returns the path of the keymap file which is used if `--keymap` isn't
+/// given.
+pub fn default_path() -> Option<std::path::PathBuf> {
+    ttyrec_bin::config::dir().map(|dir| dir.join("keymap.toml"))
+}
+
+/// Changes to the default key bindings, as read from a keymap file.
+pub type Overrides = std::collections::BTreeMap<Action, Keys>;
+
+/// Which action each key is bound to.
+pub struct Keymap {
+    // in the same order as DEFAULTS, with the key names as written
+    bindings: Vec<(Action, Vec<String>)>,
+    actions: std::collections::HashMap<Vec<u8>, Action>,
+}
+
+impl Keymap {
+    /// Builds a keymap from the default bindings with `overrides` applied.
+    /// An action which is overridden is only bound to the keys given for it
+    /// (so an empty list unbinds it), and keys which are given for one
+    /// action are removed from the defaults of every other action.
+    pub fn new(overrides: &Overrides) -> anyhow::Result<Self> {
+        let mut overridden = std::collections::HashMap::new();
+        for (&action, keys) in overrides {
+            for name in keys.names() {
+                let bytes = encode(name)?;
+                if overridden
+                    .insert(bytes, action)
+                    .is_some_and(|a| a != action)
+                {
+                    anyhow::bail!("key {name:?} is bound more than once");
+                }
+            }
+        }
+
+        let bindings: Vec<_> = DEFAULTS
+            .iter()
+            .map(|&(action, defaults)| {
+                let names = overrides.get(&action).map_or_else(
+                    || {
+                        defaults
+                            .iter()
+                            .filter(|name| {
+                                // default key names are always valid
+                                !overridden
+                                    .contains_key(&encode(name).unwrap())
+                            })
+                            .map(ToString::to_string)
+                            .collect()
+                    },
+                    |keys| keys.names().to_vec(),
+                );
+                (action, names)
+            })
+            .collect();
+
+        let mut actions = std::collections::HashMap::new();
+        for (action, names) in &bindings {
+            for name in names {
+                actions.insert(encode(name)?, *action);
+            }
+        }
+
+        Ok(Self { bindings, actions })
+    }
+
+    /// Reads a keymap file, which is a TOML table of action names to keys
+    /// (like `pause = ["Space", "p"]`).
+    pub async fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = tokio::fs::read_to_string(path).await?;
+        let overrides: Overrides = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        Self::new(&overrides)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+
+    pub fn action(&self, key: textmode::Key) -> Option<Action> {
+        self.actions.get(&key.into_bytes()).copied()
+    }
+
+    /// Returns the lines of the help overlay, all padded to the same width.
+    pub fn help(&self) -> Vec<String> {
+        let keys = |action: Action| {
+            self.bindings
+                .iter()
+                .find(|(a, _)| *a == action)
+                .map(|(_, names)| {
+                    names
+                        .iter()
+                        .map(|name| display_name(name))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .unwrap_or_default()
+        };
+        let mut lines: Vec<String> = HELP
+            .iter()
+            .filter_map(|(actions, description)| {
+                let keys: Vec<_> = actions
+                    .iter()
+                    .map(|&action| keys(action))
+                    .filter(|keys| !keys.is_empty())
+                    .collect();
+                if keys.is_empty() {
+                    return None;
+                }
+                Some(format!(
+                    " {:6} {description}",
+                    format!("{}:", keys.join("/"))
+                ))
+            })
+            .collect();
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        lines.insert(0, format!("{:^width$}", "keys"));
+        lines
+            .into_iter()
+            .map(|line| format!("{line:width$}"))
+            .collect()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        // the default bindings are always valid
+        Self::new(&Overrides::new()).unwrap()
+    }
+}
+
+// the arrow keys are shown as arrows to keep the help overlay narrow
+fn display_name(name: &str) -> &str {
+    match name {
+        "Up" => "\u{2191}",
+        "Down" => "\u{2193}",
+        "Left" => "\u{2190}",
+        "Right" => "\u{2192}",
+        _ => name,
+    }
+}
+
+fn encode(name: &str) -> anyhow::Result<Vec<u8>> {
+    ttyrec_bin::keys::encode(name)
+        .ok_or_else(|| anyhow::anyhow!("unknown key {name:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    fn keymap(toml: &str) -> anyhow::Result<super::Keymap> {
+        let overrides: super::Overrides = toml::from_str(toml)?;
+        super::Keymap::new(&overrides)
+    }
+
+    fn action(keymap: &super::Keymap, key: &str) -> Option<super::Action> {
+        keymap.action(textmode::Key::Bytes(
+            ttyrec_bin::keys::encode(key).unwrap(),
+        ))
+    }
+
+    #[test]
+    fn defaults() {
+        let keymap = super::Keymap::default();
+        assert_eq!(action(&keymap, "q"), Some(super::Action::Quit));
+        assert_eq!(action(&keymap, "Space"), Some(super::Action::Pause));
+        assert_eq!(action(&keymap, "PgUp"), Some(super::Action::BackLong));
+        assert_eq!(action(&keymap, "Up"), Some(super::Action::PanUp));
+        assert_eq!(action(&keymap, "x"), None);
+    }
+
+    #[test]
+    fn overrides() {
+        let keymap = keymap(
+            "pause = [\"p\", \"Space\"]\n\
+            quit = \"C-c\"\n\
+            back-5s = \"h\"\n\
+            toggle-help = []\n",
+        )
+        .unwrap();
+        assert_eq!(action(&keymap, "p"), Some(super::Action::Pause));
+        assert_eq!(action(&keymap, "Space"), Some(super::Action::Pause));
+        // an overridden action loses its default keys
+        assert_eq!(action(&keymap, "C-c"), Some(super::Action::Quit));
+        assert_eq!(action(&keymap, "q"), None);
+        // a key which was bound to something else is taken over, and that
+        // action keeps the rest of its defaults
+        assert_eq!(action(&keymap, "h"), Some(super::Action::BackShort));
+        assert_eq!(action(&keymap, ","), None);
+        assert_eq!(action(&keymap, "l"), Some(super::Action::NextFrame));
+        assert!(keymap.bindings.iter().any(|(action, names)| {
+            *action == super::Action::PreviousFrame && names.is_empty()
+        }));
+        // an empty list unbinds the action
+        assert_eq!(action(&keymap, "?"), None);
+        assert!(!keymap.help().iter().any(|line| line.contains("help")));
+    }
+
+    #[test]
+    fn help() {
+        let help = keymap("quit = [\"x\", \"Up\"]").unwrap().help();
+        let width = help[0].chars().count();
+        assert!(help.iter().all(|line| line.chars().count() == width));
+        assert!(help[0].contains("keys"));
+        assert!(help.iter().any(|line| {
+            line.starts_with(" x \u{2191}:")
+                && line.trim_end().ends_with("quit")
+        }));
+    }
+
+    #[test]
+    fn errors() {
+        // unknown actions and keys, and keys bound to several actions
+        assert!(keymap("explode = \"x\"").is_err());
+        assert!(keymap("quit = \"Hyper-x\"").is_err());
+        assert!(keymap("quit = 5").is_err());
+        assert!(keymap("quit = \"x\"\npause = \"x\"").is_err());
+        // binding the same key twice to the same action is fine
+        assert!(keymap("quit = [\"x\", \"x\"]").is_ok());
+    }
+}
@@ -12,11 +12,17 @@
 #![allow(clippy::type_complexity)]
 
 use clap::Parser as _;
+use textmode::Textmode as _;
 
+mod commands;
 mod display;
 mod event;
 mod frames;
+mod index;
 mod input;
+mod keymap;
+mod mouse;
+mod time;
 mod timer;
 
 #[derive(Debug, clap::Parser)]
@@ -41,7 +47,8 @@ struct Opt {
 
     #[arg(
         long,
-        help = "Restrict time between frames to at most this many milliseconds"
+        help = "Restrict time between frames to at most this many \
+            milliseconds [default: as set in the config file]"
     )]
     clamp: Option<u64>,
 
@@ -51,11 +58,119 @@ struct Opt {
     #[arg(
         short,
         long,
-        default_value = "4",
         help = "Speed to run the playback at. This can be a number from 0-8, \
-            where higher is faster."
+            where higher is faster. [default: 4, or as set in the config \
+            file]"
     )]
-    speed: u32,
+    speed: Option<u32>,
+
+    #[arg(
+        long,
+        default_value = "100",
+        help = "Number of frames between saved screen states. Lower values \
+            make seeking faster, but use more memory."
+    )]
+    keyframe_interval: usize,
+
+    #[arg(
+        long,
+        value_name = "MB",
+        help = "Approximate limit on the amount of memory to use for \
+            saved screen states, which make seeking faster. They will be \
+            discarded as needed to stay under this limit, making seeking \
+            slower. This doesn't include the recording itself, which is \
+            kept in memory unless it was loaded using a saved index."
+    )]
+    max_memory: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Keep reading the file as it is written to, like `tail -f`"
+    )]
+    follow: bool,
+
+    #[arg(
+        long,
+        value_name = "ADDR",
+        conflicts_with_all = ["follow", "index"],
+        help = "Watch a live broadcast from `ttyrec --listen` at this address \
+            (either HOST:PORT or the path to a Unix socket) instead of \
+            reading a file"
+    )]
+    connect: Option<ttyrec_bin::broadcast::Address>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["follow", "connect", "index"],
+        help = "Read timing information for a typescript recorded by \
+            `script` from this file, and read the typescript itself from the \
+            file given by --file"
+    )]
+    timing: Option<std::ffi::OsString>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = time::parse_position,
+        conflicts_with_all = ["follow", "connect"],
+        help = "Start playback at this point in the recording, either as \
+            [[HOURS:]MINUTES:]SECONDS or as a percentage like 50%"
+    )]
+    start_at: Option<time::Position>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = time::parse_position,
+        conflicts_with_all = ["follow", "connect"],
+        help = "Stop playback at this point in the recording (in the same \
+            format as --start-at)"
+    )]
+    stop_at: Option<time::Position>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read key bindings from this file instead of the one set in \
+            the config file, or keymap.toml in the config directory \
+            (~/.config/ttyrec-bin on Linux)"
+    )]
+    keymap: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read defaults from this file instead of config.toml in the \
+            config directory (~/.config/ttyrec-bin on Linux)"
+    )]
+    config: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        help = "Print the settings which would be used, as a config file, \
+            and exit"
+    )]
+    print_config: bool,
+
+    #[arg(
+        long,
+        help = "Don't use or update the cached seek index for this file"
+    )]
+    no_index: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Manage the cached seek index instead of playing the file"
+    )]
+    index: Option<index::Command>,
+}
+
+enum Source {
+    File(tokio::fs::File, Option<ttyrec_bin::format::Format>),
+    Stream(ttyrec_bin::broadcast::Stream),
+    Typescript(ttyrec_bin::broadcast::Stream, tokio::fs::File),
 }
 
 #[tokio::main]
@@ -65,26 +180,193 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
         clamp,
         paused,
         speed,
+        keyframe_interval,
+        max_memory,
+        follow,
+        connect,
+        timing,
+        start_at,
+        stop_at,
+        keymap,
+        config,
+        print_config,
+        no_index,
+        index,
     } = opt;
 
-    let speed = speed.clamp(0, 8);
+    let config = ttyrec_bin::config::Config::load(config.as_deref()).await?;
+    let clamp = clamp.or(config.ttyplay.clamp);
+    let speed = speed.or(config.ttyplay.speed).unwrap_or(4).clamp(0, 8);
+    let keymap_path = keymap
+        .or(config.ttyplay.keymap)
+        .or_else(|| keymap::default_path().filter(|path| path.exists()));
+    let colors = config.ttyplay.colors;
+
+    if print_config {
+        let config = ttyrec_bin::config::Config {
+            ttyplay: ttyrec_bin::config::Ttyplay {
+                clamp,
+                speed: Some(speed),
+                keymap: keymap_path,
+                colors,
+            },
+            ..Default::default()
+        };
+        print!("{}", config.to_toml()?);
+        return Ok(());
+    }
 
-    let fh = tokio::fs::File::open(file).await?;
+    let path = std::path::PathBuf::from(file);
+
+    if let Some(command) = index {
+        return run_index_command(command, path, keyframe_interval).await;
+    }
+
+    let source = if let Some(addr) = &connect {
+        Source::Stream(ttyrec_bin::broadcast::connect(addr).await?)
+    } else if let Some(timing) = timing {
+        let mut fh = tokio::fs::File::open(&path).await?;
+        let timing_fh = tokio::fs::File::open(timing).await?;
+        let log: ttyrec_bin::broadcast::Stream = if let Some(compression) =
+            ttyrec_bin::compression::Compression::detect(&mut fh).await?
+        {
+            compression.decoder(fh)
+        } else {
+            Box::pin(fh)
+        };
+        Source::Typescript(log, timing_fh)
+    } else {
+        let mut fh = tokio::fs::File::open(&path).await?;
+        if let Some(compression) =
+            ttyrec_bin::compression::Compression::detect(&mut fh).await?
+        {
+            if follow {
+                anyhow::bail!("--follow can't be used with compressed files");
+            }
+            Source::Stream(compression.decoder(fh))
+        } else {
+            let format =
+                ttyrec_bin::format::Format::detect_file(&mut fh).await?;
+            Source::File(fh, format)
+        }
+    };
+    let live = follow || connect.is_some();
+    let keys_path = ttyrec_bin::keys::path(&path);
+    let keys = if connect.is_none() && keys_path.exists() {
+        Some(ttyrec_bin::keys::load(&keys_path).await?)
+    } else {
+        None
+    };
+    // the metadata and bookmarks files are optional extras, so problems
+    // reading them are shown in their overlays rather than stopping the
+    // recording from being played
+    let metadata_path = ttyrec_bin::metadata::path(&path);
+    let metadata = if connect.is_none() && metadata_path.exists() {
+        ttyrec_bin::metadata::Metadata::load(&metadata_path)
+            .await
+            .map(Some)
+            .map_err(|e| format!("{}: {e}", metadata_path.display()))
+    } else {
+        Ok(None)
+    };
+    // bookmarks added while watching a broadcast have nowhere to be saved
+    let bookmarks_path = connect
+        .is_none()
+        .then(|| ttyrec_bin::bookmarks::path(&path));
+    let bookmarks = match &bookmarks_path {
+        Some(bookmarks_path) if bookmarks_path.exists() => {
+            ttyrec_bin::bookmarks::load(bookmarks_path)
+                .await
+                .map_err(|e| format!("{}: {e}", bookmarks_path.display()))
+        }
+        _ => Ok(vec![]),
+    };
+
+    let keymap = if let Some(path) = &keymap_path {
+        keymap::Keymap::load(path).await?
+    } else {
+        keymap::Keymap::default()
+    };
+    let help = keymap.help();
 
     let mut input = textmode::blocking::Input::new()?;
+    input.parse_single(false);
     let mut output = textmode::Output::new().await?;
     let _input_guard = input.take_raw_guard();
     let _output_guard = output.take_screen_guard();
+    let _mouse_guard = mouse::Guard::new()?;
 
     let (event_w, event_r) = tokio::sync::mpsc::unbounded_channel();
     let (timer_w, timer_r) = tokio::sync::mpsc::unbounded_channel();
 
-    input::spawn_thread(event_w.clone(), input);
+    input::spawn_thread(event_w.clone(), input, keymap);
 
-    let frame_data = std::sync::Arc::new(tokio::sync::Mutex::new(
-        frames::FrameData::new(),
-    ));
-    frames::load_from_file(frame_data.clone(), fh, event_w.clone(), clamp);
+    {
+        let mut signals = tokio::signal::unix::signal(
+            tokio::signal::unix::SignalKind::window_change(),
+        )?;
+        let event_w = event_w.clone();
+        tokio::task::spawn(async move {
+            while signals.recv().await.is_some() {
+                event_w
+                    .send(event::Event::Resize(
+                        terminal_size::terminal_size().map_or(
+                            (24, 80),
+                            |(
+                                terminal_size::Width(w),
+                                terminal_size::Height(h),
+                            )| { (h, w) },
+                        ),
+                    ))
+                    // event_w is never closed, so this can never fail
+                    .unwrap();
+            }
+        });
+    }
+
+    let frame_data =
+        std::sync::Arc::new(tokio::sync::Mutex::new(frames::FrameData::new(
+            output.screen().size(),
+            keyframe_interval,
+            max_memory.map(|mb| mb.saturating_mul(1024 * 1024)),
+        )));
+    match source {
+        Source::File(fh, format) => {
+            let ttyrec = format == Some(ttyrec_bin::format::Format::Ttyrec);
+            if ttyrec && !follow {
+                // frame offsets only correspond to file offsets for ttyrec
+                // files, so this is the only case where we can tell how
+                // much is left to load
+                frame_data
+                    .lock()
+                    .await
+                    .set_expected_len(fh.metadata().await?.len());
+            }
+            frames::load_from_file(
+                frame_data.clone(),
+                fh,
+                path,
+                event_w.clone(),
+                clamp,
+                // files which are still being written to can't be indexed
+                !no_index && !follow && ttyrec,
+                follow,
+            );
+        }
+        Source::Stream(stream) => frames::load_from_stream(
+            frame_data.clone(),
+            stream,
+            event_w.clone(),
+            clamp,
+        ),
+        Source::Typescript(log, timing_fh) => frames::load_from_typescript(
+            frame_data.clone(),
+            log,
+            timing_fh,
+            event_w.clone(),
+            clamp,
+        ),
+    }
 
     let timer_task = timer::spawn_task(
         event_w.clone(),
@@ -92,9 +374,24 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
         timer_r,
         paused,
         speed,
+        live,
+        start_at,
+        stop_at,
     );
 
-    event::handle_events(event_r, timer_w.clone(), output).await?;
+    event::handle_events(
+        event_r,
+        timer_w.clone(),
+        output,
+        live,
+        keys,
+        metadata,
+        bookmarks,
+        bookmarks_path,
+        help,
+        colors,
+    )
+    .await?;
 
     timer_w.send(event::TimerAction::Quit)?;
     timer_task.await?;
@@ -102,13 +399,85 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
     Ok(())
 }
 
+async fn run_index_command(
+    command: index::Command,
+    path: std::path::PathBuf,
+    keyframe_interval: usize,
+) -> anyhow::Result<()> {
+    match command {
+        index::Command::Build => {
+            let mut fh = tokio::fs::File::open(&path).await?;
+            if ttyrec_bin::compression::Compression::detect(&mut fh)
+                .await?
+                .is_some()
+                || ttyrec_bin::format::Format::detect_file(&mut fh).await?
+                    != Some(ttyrec_bin::format::Format::Ttyrec)
+            {
+                // frames in other kinds of files can't be read directly from
+                // their offsets, so there's nothing useful to save
+                anyhow::bail!(
+                    "only uncompressed ttyrec files can be indexed"
+                );
+            }
+            let size = terminal_size::terminal_size().map_or(
+                (24, 80),
+                |(terminal_size::Width(w), terminal_size::Height(h))| (h, w),
+            );
+            let frame_data = std::sync::Arc::new(tokio::sync::Mutex::new(
+                frames::FrameData::new(size, keyframe_interval, None),
+            ));
+            let (event_w, mut event_r) =
+                tokio::sync::mpsc::unbounded_channel();
+            frames::load_from_file(
+                frame_data.clone(),
+                fh,
+                path.clone(),
+                event_w,
+                None,
+                false,
+                false,
+            );
+            while let Some(event) = event_r.recv().await {
+                if matches!(event, event::Event::FrameLoaded(None)) {
+                    break;
+                }
+            }
+            let index = frame_data.lock().await.to_index();
+            let index = tokio::task::spawn_blocking(move || {
+                frames::save_index(&path, index)
+            })
+            .await??;
+            println!(
+                "indexed {} frames ({} keyframes)",
+                index.frames.len(),
+                index.keyframes.len()
+            );
+        }
+        index::Command::Verify => {
+            let index =
+                tokio::task::spawn_blocking(move || index::verify(&path))
+                    .await??;
+            println!(
+                "index is up to date ({} frames, {} keyframes)",
+                index.frames.len(),
+                index.keyframes.len()
+            );
+        }
+        index::Command::Prune => {
+            let pruned = tokio::task::spawn_blocking(index::prune).await??;
+            println!("removed {pruned} stale indexes");
+        }
+    }
+    Ok(())
+}
+
 fn main() {
     let opt = Opt::parse();
     match async_main(opt) {
-        Ok(_) => (),
+        Ok(()) => (),
         Err(e) => {
             eprintln!("ttyplay: {e}");
             std::process::exit(1);
         }
-    };
+    }
 }
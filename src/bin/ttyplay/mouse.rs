@@ -0,0 +1,163 @@
+use std::io::Write as _;
+
+// report button presses, and motion while a button is held, using the SGR
+// encoding (which has no limit on the size of the terminal)
+const ENABLE: &[u8] = b"\x1b[?1002h\x1b[?1006h";
+const DISABLE: &[u8] = b"\x1b[?1006l\x1b[?1002l";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Press,
+    Drag,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mouse {
+    pub button: Button,
+    pub kind: Kind,
+    /// The zero-based row and column of the terminal cell.
+    pub pos: (u16, u16),
+}
+
+/// Parses the part of an SGR mouse report after the initial escape, like
+/// `[<0;12;5M`.
+pub fn parse(s: &str) -> Option<Mouse> {
+    let s = s.strip_prefix("[<")?;
+    let (s, release) = if let Some(s) = s.strip_suffix('M') {
+        (s, false)
+    } else {
+        (s.strip_suffix('m')?, true)
+    };
+    let mut params = s.split(';').map(str::parse::<u16>);
+    let code = params.next()?.ok()?;
+    let col = params.next()?.ok()?;
+    let row = params.next()?.ok()?;
+    if params.next().is_some() {
+        return None;
+    }
+
+    // the low two bits are the button, and the rest are modifiers, except
+    // for 32 (motion) and 64 (wheel)
+    let button = match (code & 64 != 0, code & 3) {
+        (false, 0) => Button::Left,
+        (false, 1) => Button::Middle,
+        (false, 2) => Button::Right,
+        (true, 0) => Button::WheelUp,
+        (true, 1) => Button::WheelDown,
+        _ => return None,
+    };
+    let kind = if release {
+        Kind::Release
+    } else if code & 32 != 0 {
+        Kind::Drag
+    } else {
+        Kind::Press
+    };
+    Some(Mouse {
+        button,
+        kind,
+        pos: (row.checked_sub(1)?, col.checked_sub(1)?),
+    })
+}
+
+/// Enables mouse reporting in the terminal, and disables it again when
+/// dropped.
+pub struct Guard;
+
+impl Guard {
+    pub fn new() -> std::io::Result<Self> {
+        write_stdout(ENABLE)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // there is nothing useful to do about errors here, since this is
+        // only dropped when exiting
+        let _ = write_stdout(DISABLE);
+    }
+}
+
+fn write_stdout(buf: &[u8]) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(buf)?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    fn mouse(
+        button: super::Button,
+        kind: super::Kind,
+        pos: (u16, u16),
+    ) -> super::Mouse {
+        super::Mouse { button, kind, pos }
+    }
+
+    #[test]
+    fn parse_buttons() {
+        assert_eq!(
+            super::parse("[<0;12;5M"),
+            Some(mouse(super::Button::Left, super::Kind::Press, (4, 11)))
+        );
+        assert_eq!(
+            super::parse("[<0;12;5m"),
+            Some(mouse(super::Button::Left, super::Kind::Release, (4, 11)))
+        );
+        assert_eq!(
+            super::parse("[<1;1;1M"),
+            Some(mouse(super::Button::Middle, super::Kind::Press, (0, 0)))
+        );
+        assert_eq!(
+            super::parse("[<2;300;200M"),
+            Some(mouse(super::Button::Right, super::Kind::Press, (199, 299)))
+        );
+        assert_eq!(
+            super::parse("[<32;3;4M"),
+            Some(mouse(super::Button::Left, super::Kind::Drag, (3, 2)))
+        );
+        assert_eq!(
+            super::parse("[<64;1;1M"),
+            Some(mouse(super::Button::WheelUp, super::Kind::Press, (0, 0)))
+        );
+        assert_eq!(
+            super::parse("[<65;1;1M"),
+            Some(mouse(super::Button::WheelDown, super::Kind::Press, (0, 0)))
+        );
+        // modifiers are ignored
+        assert_eq!(
+            super::parse("[<16;2;2M"),
+            Some(mouse(super::Button::Left, super::Kind::Press, (1, 1)))
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for s in [
+            "",
+            "[<",
+            "[<0;1;1",
+            "[0;1;1M",
+            "[<0;1M",
+            "[<0;1;1;1M",
+            "[<a;1;1M",
+            "[<0;0;1M",
+            "[<3;1;1M",
+            "[<66;1;1M",
+            "[<0;1;1X",
+        ] {
+            assert_eq!(super::parse(s), None, "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,132 @@
+/// A point in a recording, either as a time since the start of playback or
+/// as a percentage of the way through it.
+#[derive(Debug, Clone, Copy)]
+pub enum Position {
+    Time(std::time::Duration),
+    Percent(f64),
+}
+
+/// Parses a position like `1:23:45`, `23:45.5`, `45` or `50%`.
+pub fn parse_position(s: &str) -> anyhow::Result<Position> {
+    let s = s.trim();
+    if let Some(percent) = s.strip_suffix('%') {
+        let percent: f64 = percent.trim().parse()?;
+        if !(0.0..=100.0).contains(&percent) {
+            anyhow::bail!("percentage must be between 0 and 100");
+        }
+        return Ok(Position::Percent(percent));
+    }
+
+    let mut parts = s.rsplit(':');
+    // rsplit always returns at least one item
+    let secs = parts.next().unwrap();
+    let mins = parts.next();
+    let hours = parts.next();
+    if parts.next().is_some() {
+        anyhow::bail!("expected [[HOURS:]MINUTES:]SECONDS");
+    }
+    let secs: f64 = secs.parse()?;
+    let mins: u64 = mins.map_or(Ok(0), str::parse)?;
+    let hours: u64 = hours.map_or(Ok(0), str::parse)?;
+    let too_large = || anyhow::anyhow!("position is too large");
+    let whole = hours
+        .checked_mul(3600)
+        .and_then(|secs| secs.checked_add(mins.checked_mul(60)?))
+        .ok_or_else(too_large)?;
+    Ok(Position::Time(
+        std::time::Duration::try_from_secs_f64(secs)?
+            .checked_add(std::time::Duration::from_secs(whole))
+            .ok_or_else(too_large)?,
+    ))
+}
+
+/// Formats a time as `M:SS.mmm`, or `H:MM:SS.mmm` if it is at least an
+/// hour.
+pub fn format(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    let millis = duration.subsec_millis();
+    if secs >= 3600 {
+        format!(
+            "{}:{:02}:{:02}.{millis:03}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    } else {
+        format!("{}:{:02}.{millis:03}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn time(s: &str) -> std::time::Duration {
+        match super::parse_position(s).unwrap() {
+            super::Position::Time(time) => time,
+            super::Position::Percent(_) => panic!("{s} is a percentage"),
+        }
+    }
+
+    fn percent(s: &str) -> f64 {
+        match super::parse_position(s).unwrap() {
+            super::Position::Percent(percent) => percent,
+            super::Position::Time(_) => panic!("{s} is a time"),
+        }
+    }
+
+    #[test]
+    fn parse_times() {
+        assert_eq!(time("45"), std::time::Duration::from_secs(45));
+        assert_eq!(time("1:30"), std::time::Duration::from_secs(90));
+        assert_eq!(
+            time("23:45.5"),
+            std::time::Duration::from_millis(1_425_500)
+        );
+        assert_eq!(time("1:02:03"), std::time::Duration::from_secs(3723));
+        assert_eq!(time(" 0:05 "), std::time::Duration::from_secs(5));
+        // seconds aren't limited to 60, and minutes can be left out
+        assert_eq!(time("90"), std::time::Duration::from_secs(90));
+    }
+
+    #[test]
+    fn parse_percentages() {
+        assert!((percent("50%") - 50.0).abs() < f64::EPSILON);
+        assert!((percent("0%")).abs() < f64::EPSILON);
+        assert!((percent("12.5 %") - 12.5).abs() < f64::EPSILON);
+        assert!((percent("100%") - 100.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for s in ["", "abc", "1:2:3:4", "-5", "1:-2", "101%", "-1%", "x%"] {
+            assert!(super::parse_position(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_overflow() {
+        assert!(super::parse_position("5124095576030431:0:15").is_ok());
+        for s in [
+            "18446744073709551615:0:0",
+            "0:18446744073709551615:0",
+            "5124095576030432:0:0",
+            "5124095576030431:1:0",
+            "5124095576030431:0:16",
+            "0:0:1e300",
+        ] {
+            assert!(super::parse_position(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(super::format(std::time::Duration::ZERO), "0:00.000");
+        assert_eq!(
+            super::format(std::time::Duration::from_millis(83_250)),
+            "1:23.250"
+        );
+        assert_eq!(
+            super::format(std::time::Duration::from_secs(3723)),
+            "1:02:03.000"
+        );
+    }
+}
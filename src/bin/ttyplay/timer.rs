@@ -8,10 +8,13 @@ pub fn spawn_task(
     >,
     pause_at_start: bool,
     speed: u32,
+    follow: bool,
+    start_at: Option<crate::time::Position>,
+    stop_at: Option<crate::time::Position>,
 ) -> tokio::task::JoinHandle<()> {
     tokio::task::spawn(async move {
         enum Res {
-            Frame(Option<Box<vt100::Screen>>),
+            Frame(Option<Box<crate::frames::Frame>>),
             Action(Option<crate::event::TimerAction>),
         }
 
@@ -27,19 +30,87 @@ pub fn spawn_task(
             None
         };
         let mut force_update_time = false;
+        // playback is treated as ending at this frame
+        let mut stop = usize::MAX;
+        if start_at.is_some() || stop_at.is_some() {
+            let resolve = async {
+                let start = match start_at {
+                    Some(position) => {
+                        Some(resolve_position(&frames, position).await)
+                    }
+                    None => None,
+                };
+                let stop = match stop_at {
+                    Some(position) => {
+                        Some(resolve_position(&frames, position).await)
+                    }
+                    None => None,
+                };
+                (start, stop)
+            };
+            let mut resolve = std::pin::pin!(resolve);
+            loop {
+                match futures_util::future::select(
+                    resolve.as_mut(),
+                    timer_r.recv().boxed(),
+                )
+                .await
+                {
+                    futures_util::future::Either::Left(((start, end), _)) => {
+                        if let Some(start) = start {
+                            idx = start;
+                            force_update_time = true;
+                        }
+                        if let Some(end) = end {
+                            stop = end;
+                        }
+                        break;
+                    }
+                    futures_util::future::Either::Right((action, _)) => {
+                        match action {
+                            Some(crate::event::TimerAction::Pause) => {
+                                let now = std::time::Instant::now();
+                                paused_time.take().map_or_else(
+                                    || {
+                                        paused_time = Some(now);
+                                    },
+                                    |time| {
+                                        start_time += now - time;
+                                    },
+                                );
+                                event_w
+                                    .send(crate::event::Event::Paused(
+                                        paused_time.is_some(),
+                                    ))
+                                    // event_w is never closed, so this can
+                                    // never fail
+                                    .unwrap();
+                            }
+                            Some(crate::event::TimerAction::Quit) | None => {
+                                return;
+                            }
+                            // nothing else means anything until we know
+                            // where playback starts
+                            Some(_) => {}
+                        }
+                    }
+                }
+            }
+        }
+        // when following a file which is still being written, this keeps
+        // playback at the most recent frame rather than replaying new
+        // frames with their original timing
+        let mut live = false;
+        let mut last_search: Option<String> = None;
         let mut playback_ratio = 2_u32.pow(speed);
         loop {
             let wait = async {
                 let wait_read =
                     frames.clone().lock_owned().await.wait_for_frame(idx);
-                if wait_read.await {
-                    let frame = frames
-                        .clone()
-                        .lock_owned()
+                if wait_read.await && idx <= stop {
+                    let frame = crate::frames::FrameData::get(&frames, idx)
                         .await
-                        .get(idx)
-                        .unwrap()
-                        .clone();
+                        .unwrap();
                     if force_update_time {
                         let now = std::time::Instant::now();
                         start_time = now
@@ -54,6 +125,12 @@ pub fn spawn_task(
                             paused_time = Some(now);
                         }
                         force_update_time = false;
+                    } else if live && paused_time.is_none() {
+                        // keep the clock in sync, so that leaving live mode
+                        // continues playing from here
+                        start_time = std::time::Instant::now()
+                            .checked_sub(frame.delay() * playback_ratio / 16)
+                            .unwrap();
                     } else if paused_time.is_some() {
                         std::future::pending::<()>().await;
                     } else {
@@ -66,7 +143,7 @@ pub fn spawn_task(
                         )
                         .await;
                     }
-                    Some(Box::new(frame.into_screen()))
+                    Some(Box::new(frame))
                 } else {
                     None
                 }
@@ -78,17 +155,23 @@ pub fn spawn_task(
             .into_iter()
             .collect();
             match select.map(|(res, _, _)| res).await {
-                Res::Frame(Some(screen)) => {
+                Res::Frame(Some(frame)) => {
                     event_w
                         .send(crate::event::Event::FrameTransition((
-                            idx, screen,
+                            idx, frame,
                         )))
                         // event_w is never closed, so this can never fail
                         .unwrap();
                     idx += 1;
                 }
                 Res::Frame(None) => {
-                    idx = frames.clone().lock_owned().await.count() - 1;
+                    idx = frames
+                        .clone()
+                        .lock_owned()
+                        .await
+                        .count()
+                        .saturating_sub(1)
+                        .min(stop);
                     paused_time = Some(std::time::Instant::now());
                     event_w
                         .send(crate::event::Event::Paused(true))
@@ -115,10 +198,18 @@ pub fn spawn_task(
                     }
                     crate::event::TimerAction::FirstFrame => {
                         idx = 0;
+                        live = false;
                         force_update_time = true;
                     }
                     crate::event::TimerAction::LastFrame => {
-                        idx = frames.clone().lock_owned().await.count() - 1;
+                        idx = frames
+                            .clone()
+                            .lock_owned()
+                            .await
+                            .count()
+                            .saturating_sub(1)
+                            .min(stop);
+                        live = follow;
                         force_update_time = true;
                     }
                     // force_update_time will immediately transition to the
@@ -128,6 +219,7 @@ pub fn spawn_task(
                     }
                     crate::event::TimerAction::PreviousFrame => {
                         idx = idx.saturating_sub(2);
+                        live = false;
                         force_update_time = true;
                     }
                     crate::event::TimerAction::SpeedUp => {
@@ -176,16 +268,63 @@ pub fn spawn_task(
                             .unwrap();
                     }
                     crate::event::TimerAction::Search(s, backwards) => {
-                        if let Some(new_idx) = frames
-                            .clone()
-                            .lock_owned()
-                            .await
-                            .search(idx, &s, backwards)
-                        {
+                        if last_search.as_ref() != Some(&s) {
+                            crate::frames::find_all(
+                                frames.clone(),
+                                s.clone(),
+                                event_w.clone(),
+                            );
+                            last_search = Some(s.clone());
+                        }
+                        let new_idx = crate::frames::FrameData::search(
+                            &frames, idx, &s, backwards,
+                        )
+                        .await;
+                        if let Some(new_idx) = new_idx {
+                            idx = new_idx;
+                            live = false;
+                            force_update_time = true;
+                        }
+                    }
+                    crate::event::TimerAction::Jump(times, backwards) => {
+                        let frames = frames.clone().lock_owned().await;
+                        let new_idx = jump_target(
+                            times
+                                .iter()
+                                .map(|&time| frames.frame_after_time(time)),
+                            idx,
+                            backwards,
+                        );
+                        drop(frames);
+                        if let Some(new_idx) = new_idx {
                             idx = new_idx;
+                            live = false;
                             force_update_time = true;
                         }
                     }
+                    crate::event::TimerAction::JumpCommand(backwards) => {
+                        let frames = frames.clone().lock_owned().await;
+                        let new_idx = jump_target(
+                            frames.commands().iter().map(|c| c.start),
+                            idx,
+                            backwards,
+                        );
+                        drop(frames);
+                        if let Some(new_idx) = new_idx {
+                            idx = new_idx;
+                            live = false;
+                            force_update_time = true;
+                        }
+                    }
+                    crate::event::TimerAction::Seek(seek) => {
+                        idx = frames
+                            .clone()
+                            .lock_owned()
+                            .await
+                            .seek(idx.saturating_sub(1), &seek);
+                        live = false;
+                        force_update_time = true;
+                    }
                     crate::event::TimerAction::Quit => break,
                 },
                 Res::Action(None) => {
@@ -195,3 +334,65 @@ pub fn spawn_task(
         }
     })
 }
+
+// returns the frame at `position`, waiting only until enough of the
+// recording has been loaded to tell which frame that is
+async fn resolve_position(
+    frames: &std::sync::Arc<tokio::sync::Mutex<crate::frames::FrameData>>,
+    position: crate::time::Position,
+) -> usize {
+    loop {
+        let data = frames.clone().lock_owned().await;
+        if data.position_loaded(position) {
+            return data.frame_at_delay(data.position_delay(position));
+        }
+        let next = match position {
+            crate::time::Position::Time(_) => data.count(),
+            crate::time::Position::Percent(_) => usize::MAX,
+        };
+        let wait_read = data.wait_for_frame(next);
+        drop(data);
+        wait_read.await;
+    }
+}
+
+// picks the first of `targets` (which must be sorted) after the current
+// frame, or the last one before it if `backwards` is set. `idx` is the next
+// frame to be shown, as tracked by the timer task.
+fn jump_target(
+    mut targets: impl Iterator<Item = usize>,
+    idx: usize,
+    backwards: bool,
+) -> Option<usize> {
+    let current = idx.saturating_sub(1);
+    if backwards {
+        targets.filter(|&i| i < current).last()
+    } else {
+        targets.find(|&i| i > current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn jump_target() {
+        let targets = [2, 5, 9];
+        let jump = |idx, backwards| {
+            super::jump_target(targets.into_iter(), idx, backwards)
+        };
+        // frame 5 is currently shown
+        assert_eq!(jump(6, false), Some(9));
+        assert_eq!(jump(6, true), Some(2));
+        // between targets
+        assert_eq!(jump(8, false), Some(9));
+        assert_eq!(jump(8, true), Some(5));
+        // nothing shown yet
+        assert_eq!(jump(0, false), Some(2));
+        assert_eq!(jump(0, true), None);
+        // past the last target
+        assert_eq!(jump(10, false), None);
+        assert_eq!(jump(10, true), Some(5));
+        assert_eq!(jump(20, true), Some(9));
+        assert_eq!(super::jump_target(std::iter::empty(), 3, false), None);
+    }
+}
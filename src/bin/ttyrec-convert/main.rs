@@ -0,0 +1,227 @@
+#![warn(clippy::cargo)]
+#![warn(clippy::pedantic)]
+#![warn(clippy::nursery)]
+#![warn(clippy::as_conversions)]
+#![warn(clippy::get_unwrap)]
+#![allow(clippy::cognitive_complexity)]
+#![allow(clippy::missing_const_for_fn)]
+#![allow(clippy::similar_names)]
+#![allow(clippy::struct_excessive_bools)]
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::too_many_lines)]
+#![allow(clippy::type_complexity)]
+
+use clap::Parser as _;
+
+#[derive(Debug, clap::Parser)]
+#[command(
+    name = "ttyrec-convert",
+    about = "Converts recordings between formats",
+    long_about = "\n\
+        This program converts terminal recordings between the ttyrec format, \
+        the asciicast format used by asciinema, and the typescript and timing \
+        files written by `script --log-timing` (which can be played back \
+        with `scriptreplay`). The format of the input file is detected \
+        automatically, except that typescripts must be given along with \
+        their timing file via the --timing option."
+)]
+struct Opt {
+    #[arg(help = "File to read the recording from")]
+    input: std::ffi::OsString,
+
+    #[arg(help = "File to write the converted recording to")]
+    output: std::ffi::OsString,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read INPUT as a typescript recorded by `script`, using the \
+            timing information in this file"
+    )]
+    timing: Option<std::ffi::OsString>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ttyrec",
+        help = "Format to write the recording in"
+    )]
+    format: Format,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        required_if_eq("format", "script"),
+        help = "File to write timing information to, when writing a \
+            typescript"
+    )]
+    output_timing: Option<std::ffi::OsString>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "advanced",
+        help = "Format of the timing file to write, when writing a \
+            typescript. Only the advanced format can record changes to the \
+            terminal size."
+    )]
+    timing_format: ttyrec_bin::typescript::TimingFormat,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Compress the output file using this format (not supported \
+            when writing a typescript)"
+    )]
+    compress: Option<ttyrec_bin::compression::Compression>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    Ttyrec,
+    Asciicast,
+    Script,
+}
+
+enum Writer {
+    Recording(ttyrec_bin::format::Writer),
+    Typescript(
+        Box<
+            ttyrec_bin::typescript::Writer<
+                tokio::io::BufWriter<tokio::fs::File>,
+                tokio::io::BufWriter<tokio::fs::File>,
+            >,
+        >,
+    ),
+}
+
+impl Writer {
+    async fn write_frame(
+        &mut self,
+        frame: ttyrec::Frame,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Recording(writer) => writer.write_frame(frame).await,
+            Self::Typescript(writer) => writer.write_frame(&frame).await,
+        }
+    }
+
+    async fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Self::Recording(writer) => writer.finish().await,
+            Self::Typescript(writer) => writer.finish().await,
+        }
+    }
+}
+
+#[tokio::main]
+async fn async_main(opt: Opt) -> anyhow::Result<()> {
+    let Opt {
+        input,
+        output,
+        timing,
+        format,
+        output_timing,
+        timing_format,
+        compress,
+    } = opt;
+
+    if format == Format::Script && compress.is_some() {
+        anyhow::bail!("--compress can't be used when writing a typescript");
+    }
+
+    let mut fh = tokio::fs::File::open(input).await?;
+    let input: ttyrec_bin::broadcast::Stream = if let Some(compression) =
+        ttyrec_bin::compression::Compression::detect(&mut fh).await?
+    {
+        compression.decoder(fh)
+    } else {
+        Box::pin(fh)
+    };
+    let mut reader = if let Some(timing) = timing {
+        ttyrec_bin::format::Reader::typescript(
+            tokio::io::BufReader::new(input),
+            tokio::fs::File::open(timing).await?,
+        )
+    } else {
+        let mut input = tokio::io::BufReader::new(input);
+        let Some(input_format) =
+            ttyrec_bin::format::Format::detect(&mut input).await?
+        else {
+            anyhow::bail!("input file is empty");
+        };
+        ttyrec_bin::format::Reader::new(input, input_format)
+    };
+
+    // every reader starts with a resize frame if it knows the size of the
+    // terminal, but ttyrec files don't record it at all
+    let first = reader.read_frame().await?;
+    let size = first
+        .as_ref()
+        .and_then(|frame| ttyrec_bin::resize::chunks(&frame.data).next())
+        .and_then(|chunk| match chunk {
+            ttyrec_bin::resize::Chunk::Resize(size) => Some(size),
+            ttyrec_bin::resize::Chunk::Data(_) => None,
+        })
+        .unwrap_or((24, 80));
+    let header = ttyrec_bin::asciicast::Header::new(size);
+
+    let fh =
+        tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let mut writer = match format {
+        Format::Ttyrec => Writer::Recording(ttyrec_bin::format::Writer::new(
+            fh,
+            ttyrec_bin::format::Format::Ttyrec,
+            compress,
+            &header,
+        )),
+        Format::Asciicast => {
+            Writer::Recording(ttyrec_bin::format::Writer::new(
+                fh,
+                ttyrec_bin::format::Format::Asciicast,
+                compress,
+                &header,
+            ))
+        }
+        Format::Script => {
+            // clap requires this when the format is script
+            let output_timing = output_timing.unwrap();
+            let timing_fh = tokio::io::BufWriter::new(
+                tokio::fs::File::create(output_timing).await?,
+            );
+            Writer::Typescript(Box::new(
+                ttyrec_bin::typescript::Writer::new(
+                    fh,
+                    timing_fh,
+                    timing_format,
+                    &header,
+                )
+                .await?,
+            ))
+        }
+    };
+
+    // ttyrec files store absolute timestamps, but the other formats are
+    // relative to the start of the recording
+    let start = first.as_ref().map(|frame| frame.time).unwrap_or_default();
+    let mut frame = first;
+    while let Some(mut next) = frame {
+        next.time = next.time.saturating_sub(start);
+        writer.write_frame(next).await?;
+        frame = reader.read_frame().await?;
+    }
+    writer.finish().await?;
+
+    Ok(())
+}
+
+fn main() {
+    let opt = Opt::parse();
+    match async_main(opt) {
+        Ok(()) => (),
+        Err(e) => {
+            eprintln!("ttyrec-convert: {e}");
+            std::process::exit(1);
+        }
+    }
+}
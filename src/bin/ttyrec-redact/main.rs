@@ -0,0 +1,514 @@
+#![warn(clippy::cargo)]
+#![warn(clippy::pedantic)]
+#![warn(clippy::nursery)]
+#![warn(clippy::as_conversions)]
+#![warn(clippy::get_unwrap)]
+#![allow(clippy::cognitive_complexity)]
+#![allow(clippy::missing_const_for_fn)]
+#![allow(clippy::similar_names)]
+#![allow(clippy::struct_excessive_bools)]
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::too_many_lines)]
+#![allow(clippy::type_complexity)]
+
+use clap::Parser as _;
+
+mod text;
+
+#[derive(Debug, clap::Parser)]
+#[command(
+    name = "ttyrec-redact",
+    about = "Removes secrets from existing recordings",
+    long_about = "\n\
+        This program writes a copy of a recording with some of its output \
+        replaced by placeholder characters, for removing secrets from \
+        recordings before sharing them. Text can be selected by regular \
+        expression, by the area of the screen it was drawn to, or by the \
+        time it was output. Escape sequences are never modified (other \
+        than regular expression matches within the text of window titles \
+        and similar sequences), and the timing of each frame is kept the \
+        same. Use --dry-run to see what \
+        would be redacted without writing anything."
+)]
+struct Opt {
+    #[arg(help = "File to read the recording from")]
+    input: std::ffi::OsString,
+
+    #[arg(
+        required_unless_present = "dry_run",
+        help = "File to write the redacted recording to"
+    )]
+    output: Option<std::ffi::OsString>,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Redact text matching this regular expression. Can be given \
+            multiple times."
+    )]
+    redact: Vec<regex::bytes::Regex>,
+
+    #[arg(
+        long,
+        help = "Redact common kinds of secrets (api keys, access tokens and \
+            private keys)"
+    )]
+    redact_secrets: bool,
+
+    #[arg(
+        long,
+        value_name = "ROW,COL,HEIGHT,WIDTH",
+        help = "Redact all text drawn to this area of the screen, where ROW \
+            and COL start at 1. Can be given multiple times."
+    )]
+    region: Vec<Region>,
+
+    #[arg(
+        long,
+        value_name = "START-END",
+        help = "Redact all text output between these two times (in seconds \
+            from the start of the recording). END can be left out to \
+            redact until the end of the recording. Can be given multiple \
+            times."
+    )]
+    time: Vec<TimeRange>,
+
+    #[arg(
+        long,
+        default_value = "*",
+        help = "Character to replace each redacted character with"
+    )]
+    placeholder: char,
+
+    #[arg(
+        long,
+        help = "List everything which would be redacted instead of writing \
+            a new file"
+    )]
+    dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    row: u16,
+    col: u16,
+    height: u16,
+    width: u16,
+}
+
+impl Region {
+    fn contains(self, (row, col): (u16, u16)) -> bool {
+        (self.row..self.row.saturating_add(self.height)).contains(&row)
+            && (self.col..self.col.saturating_add(self.width)).contains(&col)
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<u16>, _>>()?;
+        let [row, col, height, width] = fields[..] else {
+            anyhow::bail!("expected ROW,COL,HEIGHT,WIDTH");
+        };
+        if row == 0 || col == 0 {
+            anyhow::bail!("ROW and COL start at 1");
+        }
+        Ok(Self {
+            row: row - 1,
+            col: col - 1,
+            height,
+            width,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TimeRange {
+    start: std::time::Duration,
+    end: Option<std::time::Duration>,
+}
+
+impl TimeRange {
+    fn contains(self, time: std::time::Duration) -> bool {
+        time >= self.start && self.end.is_none_or(|end| time <= end)
+    }
+}
+
+impl std::str::FromStr for TimeRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((start, end)) = s.split_once('-') else {
+            anyhow::bail!("expected START-END");
+        };
+        let start = std::time::Duration::try_from_secs_f64(start.parse()?)?;
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(std::time::Duration::try_from_secs_f64(end.parse()?)?)
+        };
+        Ok(Self { start, end })
+    }
+}
+
+// something which would be redacted, for --dry-run
+struct Hit {
+    frame: usize,
+    reason: String,
+}
+
+// replaces the masked text in `data` with `placeholder`, once for each
+// column that the text took up, so that the rest of the line stays where it
+// was
+fn redact_frame(
+    data: &[u8],
+    kinds: &[text::Kind],
+    masks: &[bool],
+    placeholder: char,
+) -> Vec<u8> {
+    let mut placeholder_bytes = [0; 4];
+    let placeholder = placeholder.encode_utf8(&mut placeholder_bytes);
+    let mut redacted = Vec::with_capacity(data.len());
+    for (i, ((&c, &kind), &mask)) in
+        data.iter().zip(kinds).zip(masks).enumerate()
+    {
+        if !mask || !matches!(kind, text::Kind::Text | text::Kind::Payload) {
+            redacted.push(c);
+        } else if c & 0xc0 != 0x80 {
+            let len = 1 + data[i + 1..]
+                .iter()
+                .take_while(|&&c| c & 0xc0 == 0x80)
+                .count();
+            // characters which aren't valid utf8 (including ones split
+            // across frames) are assumed to take up a single column
+            let width = std::str::from_utf8(&data[i..i + len])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .map_or(Some(1), unicode_width::UnicodeWidthChar::width)
+                .unwrap_or(0);
+            for _ in 0..width {
+                redacted.extend(placeholder.as_bytes());
+            }
+        }
+    }
+    redacted
+}
+
+#[tokio::main]
+async fn async_main(opt: Opt) -> anyhow::Result<()> {
+    let Opt {
+        input,
+        output,
+        redact,
+        redact_secrets,
+        region,
+        time,
+        placeholder,
+        dry_run,
+    } = opt;
+
+    let rules: Vec<_> = redact
+        .into_iter()
+        .map(|regex| {
+            let name = regex.to_string();
+            ttyrec_bin::redact::Rule::new(&name, regex)
+        })
+        .chain(if redact_secrets {
+            ttyrec_bin::redact::Rule::builtin()
+        } else {
+            vec![]
+        })
+        .collect();
+
+    let mut fh = tokio::fs::File::open(input).await?;
+    let compression =
+        ttyrec_bin::compression::Compression::detect(&mut fh).await?;
+    let stream: ttyrec_bin::broadcast::Stream =
+        if let Some(compression) = compression {
+            compression.decoder(fh)
+        } else {
+            Box::pin(fh)
+        };
+    let mut stream = tokio::io::BufReader::new(stream);
+    let Some(format) =
+        ttyrec_bin::format::Format::detect(&mut stream).await?
+    else {
+        anyhow::bail!("input file is empty");
+    };
+    let mut reader = ttyrec_bin::format::Reader::new(stream, format);
+    reader.keep_events();
+    let mut frames = vec![];
+    // asciicast input and marker events, along with the index of the frame
+    // they came before, which are passed through unchanged
+    let mut events = vec![];
+    while let Some(frame) = reader.read_frame().await? {
+        let i = frames.len();
+        events.extend(reader.take_events().into_iter().map(|e| (i, e)));
+        frames.push(frame);
+    }
+    let i = frames.len();
+    events.extend(reader.take_events().into_iter().map(|e| (i, e)));
+    let Some(first) = frames.first() else {
+        anyhow::bail!("input file is empty");
+    };
+    let start = first.time;
+    let size = match ttyrec_bin::resize::chunks(&first.data).next() {
+        Some(ttyrec_bin::resize::Chunk::Resize(size)) => size,
+        _ => (24, 80),
+    };
+
+    // the text of the entire recording (without escape sequences), along
+    // with where each byte of it came from, so that matches can be found
+    // even when they are split across frames or interrupted by escape
+    // sequences
+    let mut classifier = text::Classifier::default();
+    let kinds: Vec<Vec<text::Kind>> = frames
+        .iter()
+        .map(|frame| {
+            frame.data.iter().map(|&c| classifier.classify(c)).collect()
+        })
+        .collect();
+    let mut text = vec![];
+    let mut positions = vec![];
+    // the contents of each string sequence (like a window title), which
+    // are matched separately since they aren't part of the text on the
+    // screen
+    let mut payloads: Vec<(Vec<u8>, Vec<(usize, usize)>)> = vec![];
+    let mut in_payload = false;
+    for (i, frame) in frames.iter().enumerate() {
+        for (j, &c) in frame.data.iter().enumerate() {
+            match kinds[i][j] {
+                text::Kind::Text | text::Kind::Whitespace => {
+                    text.push(c);
+                    positions.push((i, j));
+                    in_payload = false;
+                }
+                text::Kind::Payload => {
+                    if !in_payload {
+                        payloads.push((vec![], vec![]));
+                        in_payload = true;
+                    }
+                    // a payload was just pushed if there wasn't one
+                    let (payload, positions) = payloads.last_mut().unwrap();
+                    payload.push(c);
+                    positions.push((i, j));
+                }
+                text::Kind::Control => in_payload = false,
+            }
+        }
+    }
+
+    let mut masks: Vec<Vec<bool>> = frames
+        .iter()
+        .map(|frame| vec![false; frame.data.len()])
+        .collect();
+    let mut hits = vec![];
+
+    let sources = std::iter::once((&text, &positions, "")).chain(
+        payloads.iter().map(|(payload, positions)| {
+            (payload, positions, " (in an escape sequence)")
+        }),
+    );
+    for (text, positions, location) in sources {
+        for rule in &rules {
+            for range in rule.find(text, &mut false) {
+                if range.is_empty() {
+                    continue;
+                }
+                for &(i, j) in &positions[range.clone()] {
+                    masks[i][j] = true;
+                }
+                hits.push(Hit {
+                    frame: positions[range.start].0,
+                    reason: format!(
+                        "{}{location}: {:?}",
+                        rule.name(),
+                        String::from_utf8_lossy(&text[range])
+                    ),
+                });
+            }
+        }
+    }
+
+    if !time.is_empty() {
+        for (i, frame) in frames.iter().enumerate() {
+            let frame_time = frame.time.saturating_sub(start);
+            if !time.iter().any(|range| range.contains(frame_time)) {
+                continue;
+            }
+            let mut hit = false;
+            for (j, kind) in kinds[i].iter().enumerate() {
+                if *kind == text::Kind::Text {
+                    masks[i][j] = true;
+                    hit = true;
+                }
+            }
+            if hit {
+                hits.push(Hit {
+                    frame: i,
+                    reason: "time range".to_string(),
+                });
+            }
+        }
+    }
+
+    if !region.is_empty() {
+        let mut parser = vt100::Parser::new(size.0, size.1, 0);
+        for (i, frame) in frames.iter().enumerate() {
+            let data = &frame.data;
+            let mut hit = false;
+            let mut j = 0;
+            while j < data.len() {
+                if kinds[i][j] == text::Kind::Text {
+                    // a single character is processed at a time, so that
+                    // we know where each one is drawn
+                    let len = 1 + data[j + 1..]
+                        .iter()
+                        .zip(&kinds[i][j + 1..])
+                        .take_while(|&(&c, &kind)| {
+                            kind == text::Kind::Text && c & 0xc0 == 0x80
+                        })
+                        .count();
+                    let screen = parser.screen();
+                    let (rows, cols) = screen.size();
+                    let (row, col) = screen.cursor_position();
+                    // the cursor stays past the end of the line until the
+                    // next character is drawn
+                    let pos = if col >= cols {
+                        ((row + 1).min(rows.saturating_sub(1)), 0)
+                    } else {
+                        (row, col)
+                    };
+                    if region.iter().any(|region| region.contains(pos)) {
+                        for mask in &mut masks[i][j..j + len] {
+                            *mask = true;
+                        }
+                        hit = true;
+                    }
+                    parser.process(&data[j..j + len]);
+                    j += len;
+                } else {
+                    let len = kinds[i][j..]
+                        .iter()
+                        .take_while(|&&kind| kind != text::Kind::Text)
+                        .count();
+                    ttyrec_bin::resize::process(
+                        &mut parser,
+                        &data[j..j + len],
+                    );
+                    j += len;
+                }
+            }
+            if hit {
+                hits.push(Hit {
+                    frame: i,
+                    reason: "screen region".to_string(),
+                });
+            }
+        }
+    }
+
+    if dry_run {
+        hits.sort_by_key(|hit| hit.frame);
+        for hit in &hits {
+            println!(
+                "frame {} at {:.3}s: {}",
+                hit.frame + 1,
+                frames[hit.frame].time.saturating_sub(start).as_secs_f64(),
+                hit.reason
+            );
+        }
+        return Ok(());
+    }
+
+    for ((frame, kinds), masks) in frames.iter_mut().zip(&kinds).zip(&masks) {
+        frame.data = redact_frame(&frame.data, kinds, masks, placeholder);
+    }
+
+    // clap requires this unless --dry-run was given
+    let output = output.unwrap();
+    let fh =
+        tokio::io::BufWriter::new(tokio::fs::File::create(output).await?);
+    let header = reader
+        .asciicast_header()
+        .cloned()
+        .unwrap_or_else(|| ttyrec_bin::asciicast::Header::new(size));
+    let mut writer =
+        ttyrec_bin::format::Writer::new(fh, format, compression, &header);
+    let mut events = events.into_iter().peekable();
+    for (i, frame) in frames.into_iter().enumerate() {
+        while let Some((_, event)) = events.next_if(|&(j, _)| j <= i) {
+            writer.write_event(&event).await?;
+        }
+        writer.write_frame(frame).await?;
+    }
+    for (_, event) in events {
+        writer.write_event(&event).await?;
+    }
+    writer.finish().await?;
+
+    Ok(())
+}
+
+fn main() {
+    let opt = Opt::parse();
+    match async_main(opt) {
+        Ok(()) => (),
+        Err(e) => {
+            eprintln!("ttyrec-redact: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn redact(data: &str, masked: std::ops::Range<usize>) -> String {
+        let mut classifier = crate::text::Classifier::default();
+        let kinds: Vec<_> =
+            data.bytes().map(|c| classifier.classify(c)).collect();
+        let masks: Vec<_> =
+            (0..data.len()).map(|i| masked.contains(&i)).collect();
+        String::from_utf8(super::redact_frame(
+            data.as_bytes(),
+            &kinds,
+            &masks,
+            '*',
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn placeholders_match_width() {
+        assert_eq!(redact("a secret b", 2..8), "a ****** b");
+        // two columns each
+        assert_eq!(redact("a \u{5bc6}\u{7801} b", 2..8), "a **** b");
+        assert_eq!(redact("a \u{1f511} b", 2..6), "a ** b");
+        // combining characters don't take up a column of their own
+        assert_eq!(redact("a e\u{301} b", 2..5), "a * b");
+        // escape sequences are kept even when masked
+        assert_eq!(redact("\x1b[1mpw\x1b[m", 0..9), "\x1b[1m**\x1b[m");
+    }
+
+    #[test]
+    fn split_characters() {
+        let mut classifier = crate::text::Classifier::default();
+        let data = "\u{5bc6}".as_bytes();
+        let kinds: Vec<_> =
+            data.iter().map(|&c| classifier.classify(c)).collect();
+        // the start of a character whose remaining bytes are in the next
+        // frame takes up a single column
+        assert_eq!(
+            super::redact_frame(&data[..2], &kinds, &[true; 2], '*'),
+            b"*"
+        );
+        assert_eq!(
+            super::redact_frame(&data[2..], &kinds, &[true; 1], '*'),
+            b""
+        );
+    }
+}
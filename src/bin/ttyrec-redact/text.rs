@@ -0,0 +1,141 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Printable text.
+    Text,
+    /// Newlines, carriage returns and tabs, which can separate text but
+    /// shouldn't ever be redacted.
+    Whitespace,
+    /// Part of an escape sequence, or any other control character.
+    Control,
+    /// The contents of an OSC, DCS or other string sequence. These aren't
+    /// drawn on the screen, but can still contain text (like a window title
+    /// with the command being run in it).
+    Payload,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum State {
+    #[default]
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    Csi,
+    String,
+    StringEscape,
+}
+
+/// Splits terminal output into text and escape sequences. Escape sequences
+/// can be split across frames, so the same classifier needs to be used for
+/// every byte of the recording, in order.
+#[derive(Debug, Default)]
+pub struct Classifier {
+    state: State,
+}
+
+impl Classifier {
+    pub fn classify(&mut self, c: u8) -> Kind {
+        match self.state {
+            State::Ground => match c {
+                b'\x1b' => {
+                    self.state = State::Escape;
+                    Kind::Control
+                }
+                b'\n' | b'\r' | b'\t' => Kind::Whitespace,
+                0x00..=0x1f | 0x7f => Kind::Control,
+                _ => Kind::Text,
+            },
+            State::Escape => {
+                self.state = match c {
+                    b'[' => State::Csi,
+                    b']' | b'P' | b'X' | b'^' | b'_' => State::String,
+                    0x20..=0x2f => State::EscapeIntermediate,
+                    _ => State::Ground,
+                };
+                Kind::Control
+            }
+            State::EscapeIntermediate => {
+                if !(0x20..=0x2f).contains(&c) {
+                    self.state = State::Ground;
+                }
+                Kind::Control
+            }
+            State::Csi => {
+                if (0x40..=0x7e).contains(&c) {
+                    self.state = State::Ground;
+                }
+                Kind::Control
+            }
+            State::String => match c {
+                b'\x07' => {
+                    self.state = State::Ground;
+                    Kind::Control
+                }
+                b'\x1b' => {
+                    self.state = State::StringEscape;
+                    Kind::Control
+                }
+                _ => Kind::Payload,
+            },
+            State::StringEscape => {
+                // anything other than ST also ends the string, but is then
+                // the start of a new escape sequence, which we don't bother
+                // to handle
+                self.state = State::Ground;
+                Kind::Control
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Kind::{Control, Payload, Text, Whitespace};
+
+    fn classify(data: &[u8]) -> Vec<super::Kind> {
+        let mut classifier = super::Classifier::default();
+        data.iter().map(|&c| classifier.classify(c)).collect()
+    }
+
+    #[test]
+    fn text_and_csi() {
+        assert_eq!(
+            classify(b"a\x1b[1;2Hb\r\n"),
+            [
+                Text, Control, Control, Control, Control, Control, Control,
+                Text, Whitespace, Whitespace
+            ]
+        );
+    }
+
+    #[test]
+    fn osc_payloads() {
+        assert_eq!(
+            classify(b"\x1b]0;t\x07a"),
+            [Control, Control, Payload, Payload, Payload, Control, Text]
+        );
+        // terminated by ST instead of BEL
+        assert_eq!(
+            classify(b"\x1b]2;t\x1b\\a"),
+            [
+                Control, Control, Payload, Payload, Payload, Control,
+                Control, Text
+            ]
+        );
+    }
+
+    #[test]
+    fn split_across_frames() {
+        let mut classifier = super::Classifier::default();
+        let first: Vec<_> = b"a\x1b]0;x"
+            .iter()
+            .map(|&c| classifier.classify(c))
+            .collect();
+        let second: Vec<_> =
+            b"y\x07b".iter().map(|&c| classifier.classify(c)).collect();
+        assert_eq!(
+            first,
+            [Text, Control, Control, Payload, Payload, Payload]
+        );
+        assert_eq!(second, [Payload, Control, Text]);
+    }
+}
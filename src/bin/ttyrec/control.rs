@@ -0,0 +1,152 @@
+pub const HELP: &str =
+    "p: pause/resume, m: bookmark, s: status, d: stop recording, k: kill";
+
+/// What to do in response to a key pressed by the user.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Send these bytes to the child process.
+    Send(Vec<u8>),
+    TogglePause,
+    Bookmark(String),
+    Status,
+    Stop,
+    Kill,
+    Help,
+    /// The bookmark name prompt has changed, and should be redrawn.
+    Prompt(String),
+    /// The bookmark name prompt was cancelled.
+    Cancel,
+    /// Nothing to do (yet).
+    None,
+}
+
+#[derive(Debug)]
+enum State {
+    Normal,
+    Prefix,
+    Naming(String),
+}
+
+/// Tracks keys which are meant for ttyrec itself rather than the child
+/// process. These are introduced by a prefix key, like in screen or tmux.
+pub struct Control {
+    prefix: Option<Vec<u8>>,
+    state: State,
+}
+
+impl Control {
+    pub fn new(prefix: Option<Vec<u8>>) -> Self {
+        Self {
+            prefix,
+            state: State::Normal,
+        }
+    }
+
+    pub fn key(&mut self, bytes: Vec<u8>) -> Action {
+        match std::mem::replace(&mut self.state, State::Normal) {
+            State::Normal => {
+                if self.prefix.as_ref() == Some(&bytes) {
+                    self.state = State::Prefix;
+                    Action::None
+                } else {
+                    Action::Send(bytes)
+                }
+            }
+            State::Prefix => {
+                if self.prefix.as_ref() == Some(&bytes) {
+                    // pressing the prefix key twice sends it through
+                    return Action::Send(bytes);
+                }
+                match &bytes[..] {
+                    b"p" => Action::TogglePause,
+                    b"m" => {
+                        self.state = State::Naming(String::new());
+                        Action::Prompt(String::new())
+                    }
+                    b"s" => Action::Status,
+                    b"d" => Action::Stop,
+                    b"k" => Action::Kill,
+                    _ => Action::Help,
+                }
+            }
+            State::Naming(mut name) => match &bytes[..] {
+                b"\r" | b"\n" => Action::Bookmark(name),
+                b"\x1b" | b"\x03" | b"\x07" => Action::Cancel,
+                b"\x7f" | b"\x08" => {
+                    name.pop();
+                    self.state = State::Naming(name.clone());
+                    Action::Prompt(name)
+                }
+                _ => {
+                    if let Ok(s) = std::str::from_utf8(&bytes) {
+                        name.extend(s.chars().filter(|c| !c.is_control()));
+                    }
+                    self.state = State::Naming(name.clone());
+                    Action::Prompt(name)
+                }
+            },
+        }
+    }
+}
+
+/// Returns the bytes to draw `message` over the last row of a terminal of
+/// the given size, leaving the cursor where it was.
+pub fn message((rows, cols): (u16, u16), message: &str) -> Vec<u8> {
+    let message: String = message
+        .chars()
+        .take(usize::from(cols).saturating_sub(2))
+        .collect();
+    format!("\x1b7\x1b[{rows}H\x1b[m\x1b[7m\x1b[2K {message} \x1b[m\x1b8")
+        .into_bytes()
+}
+
+/// Tracks the time to record output at, which skips over any time spent
+/// paused.
+pub struct Clock {
+    start: std::time::Instant,
+    pause_start: Option<std::time::Instant>,
+    paused_time: std::time::Duration,
+}
+
+impl Clock {
+    pub fn new(start: std::time::Instant) -> Self {
+        Self {
+            start,
+            pause_start: None,
+            paused_time: std::time::Duration::ZERO,
+        }
+    }
+
+    /// Returns the time to record something happening now at.
+    pub fn now(&self) -> std::time::Instant {
+        self.start + self.elapsed()
+    }
+
+    /// Returns how much time has been recorded so far.
+    pub fn elapsed(&self) -> std::time::Duration {
+        self.pause_start
+            .unwrap_or_else(std::time::Instant::now)
+            .saturating_duration_since(self.start)
+            .saturating_sub(self.paused_time)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause_start.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        if self.pause_start.is_none() {
+            self.pause_start = Some(std::time::Instant::now());
+        }
+    }
+
+    /// Resumes the clock, returning how long it was paused for.
+    pub fn resume(&mut self) -> std::time::Duration {
+        let Some(pause_start) = self.pause_start.take() else {
+            return std::time::Duration::ZERO;
+        };
+        let paused = pause_start.elapsed();
+        self.paused_time += paused;
+        paused
+    }
+}
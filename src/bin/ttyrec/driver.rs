@@ -0,0 +1,313 @@
+// the delay between characters typed by the `type` command, unless changed
+// with the `delay` command
+const DEFAULT_DELAY: std::time::Duration =
+    std::time::Duration::from_millis(50);
+
+#[derive(Debug)]
+enum Command {
+    Type(String),
+    Send(Vec<u8>),
+    Key(Vec<Vec<u8>>),
+    Wait(regex::Regex),
+    Sleep(std::time::Duration),
+    Delay(std::time::Duration),
+    Timeout(Option<std::time::Duration>),
+    End,
+}
+
+/// A list of input to send to the child process, and output to wait for.
+#[derive(Debug)]
+pub struct Script {
+    // along with the line number each command came from
+    commands: Vec<(usize, Command)>,
+}
+
+impl Script {
+    pub async fn load(path: &std::ffi::OsStr) -> anyhow::Result<Self> {
+        let contents = tokio::fs::read_to_string(path).await?;
+        Self::parse(&contents).map_err(|e| {
+            anyhow::anyhow!("{}: {}", std::path::Path::new(path).display(), e)
+        })
+    }
+
+    fn parse(contents: &str) -> anyhow::Result<Self> {
+        let mut commands = vec![];
+        for (i, line) in contents.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let command = parse_command(line)
+                .map_err(|e| anyhow::anyhow!("line {line_number}: {e}"))?;
+            commands.push((line_number, command));
+        }
+        Ok(Self { commands })
+    }
+}
+
+fn parse_command(line: &str) -> anyhow::Result<Command> {
+    let (name, arg) = line.split_once(' ').unwrap_or((line, ""));
+    Ok(match name {
+        "type" => Command::Type(
+            String::from_utf8(unescape(arg)?)
+                .map_err(|_| anyhow::anyhow!("text must be valid utf8"))?,
+        ),
+        "send" => Command::Send(unescape(arg)?),
+        "key" => Command::Key(
+            arg.split_whitespace()
+                .map(|key| {
+                    ttyrec_bin::keys::encode(key)
+                        .ok_or_else(|| anyhow::anyhow!("unknown key {key:?}"))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        "wait" => Command::Wait(
+            regex::RegexBuilder::new(arg).multi_line(true).build()?,
+        ),
+        "sleep" => Command::Sleep(parse_duration(arg)?),
+        "delay" => Command::Delay(parse_duration(arg)?),
+        "timeout" => Command::Timeout(if arg.trim() == "none" {
+            None
+        } else {
+            Some(parse_duration(arg)?)
+        }),
+        "end" => Command::End,
+        _ => anyhow::bail!("unknown command {name:?}"),
+    })
+}
+
+fn parse_duration(s: &str) -> anyhow::Result<std::time::Duration> {
+    Ok(std::time::Duration::try_from_secs_f64(s.trim().parse()?)?)
+}
+
+fn unescape(s: &str) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        bytes.push(match chars.next() {
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('e') => b'\x1b',
+            Some('\\') => b'\\',
+            Some(c) => anyhow::bail!("unknown escape \\{c}"),
+            None => anyhow::bail!("unterminated escape"),
+        });
+    }
+    Ok(bytes)
+}
+
+/// Runs `script` against the output of the child process (as received over
+/// `output_r`, including resize sequences), sending input back as
+/// [`crate::Event::Input`].
+pub fn spawn_task(
+    event_w: tokio::sync::mpsc::UnboundedSender<crate::Event>,
+    mut output_r: tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>,
+    script: Script,
+    size: (u16, u16),
+    headless: bool,
+) {
+    tokio::task::spawn(async move {
+        let input = |bytes| {
+            event_w
+                .send(crate::Event::Input(bytes))
+                // event_w is never closed, so this can never fail
+                .unwrap();
+        };
+
+        let mut parser = vt100::Parser::new(size.0, size.1, 0);
+        let mut delay = DEFAULT_DELAY;
+        let mut timeout = None;
+        for (line_number, command) in script.commands {
+            match command {
+                Command::Type(text) => {
+                    for c in text.chars() {
+                        input(c.to_string().into_bytes());
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+                Command::Send(bytes) => input(bytes),
+                Command::Key(keys) => {
+                    for key in keys {
+                        input(key);
+                        tokio::time::sleep(delay).await;
+                    }
+                }
+                Command::Wait(regex) => {
+                    let wait = async {
+                        loop {
+                            // catch up on any output which has already
+                            // arrived before checking the screen
+                            while let Ok(data) = output_r.try_recv() {
+                                ttyrec_bin::resize::process(
+                                    &mut parser,
+                                    &data,
+                                );
+                            }
+                            if regex.is_match(&parser.screen().contents()) {
+                                return true;
+                            }
+                            let Some(data) = output_r.recv().await else {
+                                return false;
+                            };
+                            ttyrec_bin::resize::process(&mut parser, &data);
+                        }
+                    };
+                    let res = if let Some(timeout) = timeout {
+                        tokio::time::timeout(timeout, wait).await
+                    } else {
+                        Ok(wait.await)
+                    };
+                    match res {
+                        Ok(true) => {}
+                        // the recording has already ended
+                        Ok(false) => return,
+                        Err(_) => {
+                            event_w
+                                .send(crate::Event::Error(anyhow::anyhow!(
+                                    "script line {line_number}: timed out \
+                                    waiting for {regex}"
+                                )))
+                                // event_w is never closed, so this can never
+                                // fail
+                                .unwrap();
+                            return;
+                        }
+                    }
+                }
+                Command::Sleep(duration) => {
+                    tokio::time::sleep(duration).await;
+                }
+                Command::Delay(duration) => delay = duration,
+                Command::Timeout(duration) => timeout = duration,
+                Command::End => {
+                    event_w
+                        .send(crate::Event::Quit)
+                        // event_w is never closed, so this can never fail
+                        .unwrap();
+                    return;
+                }
+            }
+        }
+
+        // there is no keyboard to take over once the script is done, so the
+        // input ends here (as if ^D was typed)
+        if headless {
+            input(b"\x04".to_vec());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    fn parse_err(line: &str) -> String {
+        super::parse_command(line).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse_script() {
+        let script = super::Script::parse(
+            "# a comment\n\
+            \n\
+            type echo hi\\n\n\
+            \x20 # an indented comment\n\
+            send \\e[A\\r\\t\\\\\n\
+            key C-c Enter\n\
+            wait ^\\$ $\n\
+            sleep 1.5\n\
+            delay 0.01\n\
+            timeout 10\n\
+            timeout none\n\
+            end\n",
+        )
+        .unwrap();
+        let lines: Vec<_> = script.commands.iter().map(|(i, _)| *i).collect();
+        assert_eq!(lines, [3, 5, 6, 7, 8, 9, 10, 11, 12]);
+        let mut commands = script.commands.into_iter().map(|(_, c)| c);
+        assert!(matches!(
+            commands.next(),
+            Some(super::Command::Type(text)) if text == "echo hi\n"
+        ));
+        assert!(matches!(
+            commands.next(),
+            Some(super::Command::Send(bytes)) if bytes == b"\x1b[A\r\t\\"
+        ));
+        assert!(matches!(
+            commands.next(),
+            Some(super::Command::Key(keys))
+                if keys == [b"\x03".to_vec(), b"\r".to_vec()]
+        ));
+        let Some(super::Command::Wait(regex)) = commands.next() else {
+            panic!("expected wait");
+        };
+        // lines are matched individually
+        assert!(regex.is_match("output\n$ \nmore"));
+        assert!(!regex.is_match("a $ "));
+        assert!(matches!(
+            commands.next(),
+            Some(super::Command::Sleep(duration))
+                if duration == std::time::Duration::from_millis(1500)
+        ));
+        assert!(matches!(
+            commands.next(),
+            Some(super::Command::Delay(duration))
+                if duration == std::time::Duration::from_millis(10)
+        ));
+        assert!(matches!(
+            commands.next(),
+            Some(super::Command::Timeout(Some(duration)))
+                if duration == std::time::Duration::from_secs(10)
+        ));
+        assert!(matches!(
+            commands.next(),
+            Some(super::Command::Timeout(None))
+        ));
+        assert!(matches!(commands.next(), Some(super::Command::End)));
+        assert!(commands.next().is_none());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(super::unescape("a\\nb").unwrap(), b"a\nb");
+        assert_eq!(super::unescape("\\r\\t\\e\\\\").unwrap(), b"\r\t\x1b\\");
+        assert_eq!(super::unescape("\u{e9}").unwrap(), "\u{e9}".as_bytes());
+        assert_eq!(super::unescape("").unwrap(), b"");
+        assert!(super::unescape("a\\").is_err());
+        assert!(super::unescape("\\x41").is_err());
+        assert_eq!(parse_err("send abc\\"), "unterminated escape");
+        assert_eq!(parse_err("type \\q"), "unknown escape \\q");
+        assert_eq!(parse_err("type \\xff"), "unknown escape \\x");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_err("typo hi"), "unknown command \"typo\"");
+        assert_eq!(parse_err("key C-c Nope"), "unknown key \"Nope\"");
+        assert!(super::parse_command("wait (").is_err());
+        for line in [
+            "sleep",
+            "sleep abc",
+            "sleep -1",
+            "sleep 1e400",
+            "delay x",
+            "delay -0.5",
+            "timeout",
+            "timeout forever",
+            "timeout -3",
+        ] {
+            assert!(super::parse_command(line).is_err(), "{line:?}");
+        }
+
+        // errors from a whole script include the line number
+        let e = super::Script::parse("type a\n# b\nsleep soon\n")
+            .unwrap_err()
+            .to_string();
+        assert!(e.starts_with("line 3: "), "{e}");
+    }
+}
@@ -13,8 +13,22 @@
 
 use clap::Parser as _;
 use futures_util::StreamExt as _;
+use std::os::fd::AsFd as _;
+use std::os::fd::AsRawFd as _;
 use tokio::io::AsyncWriteExt as _;
 
+mod control;
+mod driver;
+
+// output held back by --redact is recorded once no more output has arrived
+// for this long
+const REDACT_FLUSH_DELAY: std::time::Duration =
+    std::time::Duration::from_millis(50);
+
+// how long to wait for more output after the child has exited
+const EXIT_DRAIN_TIMEOUT: std::time::Duration =
+    std::time::Duration::from_millis(50);
+
 #[derive(Debug, clap::Parser)]
 #[command(
     name = "ttyrec",
@@ -28,13 +42,175 @@ struct Opt {
     #[arg(
         short,
         long,
-        default_value = "ttyrec",
-        help = "File to save ttyrec data to"
+        help = "File to save ttyrec data to [default: ttyrec, or the file \
+            and directory set in the config file]"
     )]
-    file: std::ffi::OsString,
+    file: Option<std::ffi::OsString>,
 
     #[arg(short, long, help = "Command to run [default: $SHELL]")]
     cmd: Option<std::ffi::OsString>,
+
+    #[arg(
+        long,
+        value_name = "ADDR",
+        help = "Broadcast the session live to anyone who connects to this \
+            address (either HOST:PORT or the path to a Unix socket, \
+            optionally prefixed with unix:). The broadcast can be watched \
+            with `ttyplay --connect`."
+    )]
+    listen: Option<ttyrec_bin::broadcast::Address>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Compress the output file using this format [default: the \
+            format set in the config file, if any]"
+    )]
+    compress: Option<ttyrec_bin::compression::Compression>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ttyrec",
+        help = "Format to save the recording in"
+    )]
+    format: ttyrec_bin::format::Format,
+
+    #[arg(
+        long,
+        help = "Also record keystrokes, to a separate file named after the \
+            output file with `.keys` appended. These can be shown during \
+            playback with ttyplay's keystroke overlay. Keystrokes typed at \
+            password prompts are left out, and --redact applies to \
+            keystrokes too."
+    )]
+    keys: bool,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "Replace text matching this regular expression with `*` in \
+            the recording (but not on the terminal). Can be given multiple \
+            times."
+    )]
+    redact: Vec<regex::bytes::Regex>,
+
+    #[arg(
+        long,
+        help = "Replace common kinds of secrets (api keys, access tokens and \
+            private keys) with `*` in the recording (but not on the terminal)"
+    )]
+    redact_secrets: bool,
+
+    #[arg(
+        long,
+        help = "Run without a terminal (for instance, in CI). Keyboard input \
+            isn't read and output isn't shown (unless --echo is given), and \
+            the terminal size is set by --size."
+    )]
+    headless: bool,
+
+    #[arg(
+        long,
+        value_name = "ROWSxCOLS",
+        value_parser = parse_size,
+        help = "Terminal size to record at, instead of the size of the \
+            current terminal [default in --headless mode: 24x80]"
+    )]
+    size: Option<(u16, u16)>,
+
+    #[arg(
+        long,
+        requires = "headless",
+        help = "Show the output on stdout in --headless mode"
+    )]
+    echo: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "headless",
+        help = "Send the contents of this file as input to the command in \
+            --headless mode (followed by an end of file)"
+    )]
+    input: Option<std::ffi::OsString>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        help = "Stop recording (and exit with an error) if the command is \
+            still running after this many seconds"
+    )]
+    timeout: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "input",
+        help = "Drive the command with the input in this file, for \
+            reproducible recordings. Each line is a command: `type TEXT` \
+            types TEXT one character at a time, `send TEXT` sends TEXT all at \
+            once, `key KEY...` presses keys like `Enter`, `C-c` or `Up`, \
+            `wait REGEX` waits until REGEX matches the screen, `sleep \
+            SECONDS` pauses, `delay SECONDS` sets the time between typed \
+            characters (default 0.05), `timeout SECONDS` limits how long \
+            later `wait` commands wait for, and `end` stops the recording. \
+            TEXT can contain \\n, \\r, \\t, \\e and \\\\ escapes, and lines \
+            starting with # are ignored."
+    )]
+    script: Option<std::ffi::OsString>,
+
+    #[arg(
+        long,
+        help = "Don't save information about the session (the command, \
+            working directory, host, user, start time, terminal size and exit \
+            status) alongside the recording"
+    )]
+    no_metadata: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Don't print a summary of the recording when it finishes"
+    )]
+    quiet: bool,
+
+    #[arg(
+        long,
+        value_name = "KEY",
+        default_value = "C-]",
+        help = "Key which introduces commands for ttyrec itself, or `none`. \
+            Press it followed by p to pause or resume recording, m to add a \
+            bookmark, s to show the status of the recording, d to stop \
+            recording while leaving the command running, or k to quit \
+            (which also ends the command). Press it twice to send it to the \
+            command. Nothing is recorded while paused, but the screen is \
+            recorded as it is when recording resumes, so clear it first if \
+            it shows something which shouldn't be recorded."
+    )]
+    prefix: String,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read defaults from this config file instead of config.toml \
+            in the config directory (~/.config/ttyrec-bin on Linux)"
+    )]
+    config: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        help = "Print the settings which would be used (from the config file \
+            and the command line) and exit"
+    )]
+    print_config: bool,
+}
+
+fn parse_size(s: &str) -> anyhow::Result<(u16, u16)> {
+    let Some((rows, cols)) = s.split_once('x') else {
+        anyhow::bail!("expected ROWSxCOLS");
+    };
+    Ok((rows.parse()?, cols.parse()?))
 }
 
 fn get_cmd(
@@ -57,37 +233,196 @@ fn get_cmd(
 #[derive(Debug)]
 enum Event {
     Key(textmode::Result<Option<textmode::Key>>),
+    Input(Vec<u8>),
     Stdout(std::io::Result<bytes::Bytes>),
     Resize((u16, u16)),
+    Watcher(ttyrec_bin::broadcast::Watcher),
+    FlushRedactor,
     Error(anyhow::Error),
+    Timeout,
+    Exit(Option<std::process::ExitStatus>),
     Quit,
 }
 
 #[tokio::main]
-async fn async_main(opt: Opt) -> anyhow::Result<()> {
-    let Opt { cmd, file } = opt;
+async fn async_main(opt: Opt) -> anyhow::Result<Option<Summary>> {
+    let Opt {
+        cmd,
+        file,
+        listen,
+        compress,
+        format,
+        keys,
+        redact,
+        redact_secrets,
+        headless,
+        size,
+        echo,
+        input,
+        timeout,
+        script,
+        no_metadata,
+        quiet: _,
+        prefix,
+        config,
+        print_config,
+    } = opt;
+    let config = ttyrec_bin::config::Config::load(config.as_deref()).await?;
+    let compress = compress.or(config.ttyrec.compress);
+    if print_config {
+        let ttyrec = if let Some(file) = &file {
+            ttyrec_bin::config::Ttyrec {
+                directory: None,
+                file: Some(file.to_string_lossy().into_owned()),
+                compress,
+            }
+        } else {
+            ttyrec_bin::config::Ttyrec {
+                file: Some(
+                    config
+                        .ttyrec
+                        .file
+                        .clone()
+                        .unwrap_or_else(|| "ttyrec".to_string()),
+                ),
+                compress,
+                ..config.ttyrec
+            }
+        };
+        let config = ttyrec_bin::config::Config {
+            ttyrec,
+            ..Default::default()
+        };
+        print!("{}", config.to_toml()?);
+        return Ok(None);
+    }
+    let file = if let Some(file) = file {
+        file
+    } else {
+        if let Some(directory) = &config.ttyrec.directory {
+            tokio::fs::create_dir_all(directory).await?;
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        config.ttyrec.file_path(now).into_os_string()
+    };
+    let prefix = if prefix == "none" {
+        None
+    } else {
+        Some(
+            ttyrec_bin::keys::encode(&prefix)
+                .ok_or_else(|| anyhow::anyhow!("unknown key {prefix:?}"))?,
+        )
+    };
+    let command = cmd.as_ref().map(|cmd| cmd.to_string_lossy().into_owned());
     let (cmd, args) = get_cmd(cmd);
 
-    let fh = tokio::fs::File::create(file).await?;
+    let keys_fh = if keys {
+        Some(
+            tokio::fs::File::create(ttyrec_bin::keys::path(
+                std::path::Path::new(&file),
+            ))
+            .await?,
+        )
+    } else {
+        None
+    };
+    let rules: Vec<_> = redact
+        .into_iter()
+        .map(|regex| {
+            let name = regex.to_string();
+            ttyrec_bin::redact::Rule::new(&name, regex)
+        })
+        .chain(if redact_secrets {
+            ttyrec_bin::redact::Rule::builtin()
+        } else {
+            vec![]
+        })
+        .collect();
+    let mut redaction_log = if rules.is_empty() {
+        None
+    } else {
+        Some(
+            tokio::fs::File::create(ttyrec_bin::redact::log_path(
+                std::path::Path::new(&file),
+            ))
+            .await?,
+        )
+    };
+    let input_data = if let Some(input) = input {
+        Some(tokio::fs::read(input).await?)
+    } else {
+        None
+    };
+    let script = if let Some(script) = script {
+        Some(driver::Script::load(&script).await?)
+    } else {
+        None
+    };
+    let metadata_path = (!no_metadata)
+        .then(|| ttyrec_bin::metadata::path(std::path::Path::new(&file)));
+    // bookmarks are only written when one is added, so make sure there
+    // aren't any left over from a previous recording
+    let bookmarks_path =
+        ttyrec_bin::bookmarks::path(std::path::Path::new(&file));
+    match tokio::fs::remove_file(&bookmarks_path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(e.into());
+        }
+        _ => {}
+    }
+    let fh = tokio::fs::File::create(&file).await?;
 
-    let mut input = textmode::blocking::Input::new()?;
-    let _input_guard = input.take_raw_guard();
+    let mut keyboard = if headless {
+        None
+    } else {
+        Some(textmode::blocking::Input::new()?)
+    };
+    let _input_guard = keyboard
+        .as_mut()
+        .map(textmode::blocking::Input::take_raw_guard);
     let mut stdout = tokio::io::stdout();
+    let echo = !headless || echo;
 
-    let size = terminal_size::terminal_size().map_or(
-        (24, 80),
-        |(terminal_size::Width(w), terminal_size::Height(h))| (h, w),
-    );
+    let fixed_size = size.is_some() || headless;
+    let size = size.unwrap_or_else(|| {
+        if headless {
+            (24, 80)
+        } else {
+            terminal_size::terminal_size().map_or(
+                (24, 80),
+                |(terminal_size::Width(w), terminal_size::Height(h))| (h, w),
+            )
+        }
+    });
     let mut pty = pty_process::Pty::new()?;
     pty.resize(pty_process::Size::new(size.0, size.1))?;
     let pts = pty.pts()?;
+    // the pty itself is moved into the task which reads from it, but the
+    // terminal settings are still needed to tell when a password is typed
+    let pty_fd = pty.as_fd().try_clone_to_owned()?;
+    let mut metadata = ttyrec_bin::metadata::Metadata::new(
+        command
+            .clone()
+            .unwrap_or_else(|| cmd.to_string_lossy().into_owned()),
+        size,
+    );
+    if let Some(metadata_path) = &metadata_path {
+        metadata.write(metadata_path).await?;
+    }
     let mut child = pty_process::Command::new(cmd).args(args).spawn(&pts)?;
+    // the child itself is owned by the task waiting for it to exit
+    let child_pid =
+        child.id().and_then(|pid| libc::pid_t::try_from(pid).ok());
 
     let (event_w, mut event_r) = tokio::sync::mpsc::unbounded_channel();
     let (input_w, input_r) = tokio::sync::mpsc::unbounded_channel();
     let (resize_w, resize_r) = tokio::sync::mpsc::unbounded_channel();
 
-    {
+    // the size of the terminal we are running in doesn't matter if the
+    // size was given explicitly
+    if !fixed_size {
         let mut signals = tokio::signal::unix::signal(
             tokio::signal::unix::SignalKind::window_change(),
         )?;
@@ -110,12 +445,12 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
         });
     }
 
-    {
+    if let Some(mut keyboard) = keyboard {
         let event_w = event_w.clone();
         std::thread::spawn(move || {
             loop {
                 event_w
-                    .send(Event::Key(input.read_key()))
+                    .send(Event::Key(keyboard.read_key()))
                     // event_w is never closed, so this can never fail
                     .unwrap();
             }
@@ -185,8 +520,30 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
                                 .unwrap();
                         }
                     }
-                    Res::Exit(_) => {
-                        event_w.send(Event::Quit).unwrap();
+                    Res::Exit(status) => {
+                        // the child can exit before we have read everything
+                        // it wrote, so pick up whatever is left first
+                        while let Ok(Some(res)) = tokio::time::timeout(
+                            EXIT_DRAIN_TIMEOUT,
+                            select.next(),
+                        )
+                        .await
+                        {
+                            match res {
+                                Res::Read(Ok(bytes)) => {
+                                    event_w
+                                        .send(Event::Stdout(Ok(bytes)))
+                                        // event_w is never closed, so this
+                                        // can never fail
+                                        .unwrap();
+                                }
+                                Res::Read(Err(_)) => break,
+                                // input and resizes don't matter once the
+                                // child is gone
+                                _ => {}
+                            }
+                        }
+                        event_w.send(Event::Exit(status.ok())).unwrap();
                         break;
                     }
                 }
@@ -194,48 +551,606 @@ async fn async_main(opt: Opt) -> anyhow::Result<()> {
         });
     }
 
-    let mut writer = ttyrec::Writer::new(fh);
+    let mut broadcaster = if let Some(addr) = listen {
+        let listener = ttyrec_bin::broadcast::Listener::bind(&addr).await?;
+        let event_w = event_w.clone();
+        tokio::task::spawn(async move {
+            // if we can't accept new watchers anymore, existing watchers
+            // and the recording itself can still continue
+            while let Ok(watcher) = listener.accept().await {
+                event_w
+                    .send(Event::Watcher(watcher))
+                    // event_w is never closed, so this can never fail
+                    .unwrap();
+            }
+        });
+        Some(ttyrec_bin::broadcast::Broadcaster::new(size))
+    } else {
+        None
+    };
+
+    let header = ttyrec_bin::asciicast::Header {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|time| time.as_secs()),
+        command,
+        env: Some(
+            ["SHELL", "TERM"]
+                .into_iter()
+                .filter_map(|var| {
+                    std::env::var(var).ok().map(|val| (var.to_string(), val))
+                })
+                .collect(),
+        ),
+        ..ttyrec_bin::asciicast::Header::new(size)
+    };
+    let mut writer =
+        ttyrec_bin::format::Writer::new(fh, format, compress, &header);
+    let start = std::time::Instant::now();
+    let data = ttyrec_bin::resize::sequence(size);
+    writer.frame_at(start, &data).await?;
+    if let Some(broadcaster) = &mut broadcaster {
+        broadcaster.frame(start, &data);
+    }
+    // finished (and so taken) once recording is stopped
+    let mut writer = Some(writer);
+    let mut keys_writer =
+        keys_fh.map(|fh| ttyrec_bin::keys::Writer::new(fh, start));
+    // keystrokes are redacted separately, since they aren't part of the
+    // output (and so aren't in the redaction log either)
+    let mut keys_redactor = (keys_writer.is_some() && !rules.is_empty())
+        .then(|| ttyrec_bin::redact::Redactor::new(rules.clone(), start));
+    let mut redactor = (!rules.is_empty()).then(|| {
+        let mut redactor = ttyrec_bin::redact::Redactor::new(rules, start);
+        redactor.skip(data.len());
+        redactor
+    });
+    let driver_w = script.map(|script| {
+        let (driver_w, driver_r) = tokio::sync::mpsc::unbounded_channel();
+        driver::spawn_task(event_w.clone(), driver_r, script, size, headless);
+        driver_w
+    });
+    if headless && driver_w.is_none() {
+        // there is no keyboard, so the input ends after the input file (as
+        // if ^D was typed)
+        let mut data = input_data.unwrap_or_default();
+        data.push(b'\x04');
+        input_w.send(data).unwrap();
+    }
+    if let Some(timeout) = timeout {
+        let event_w = event_w.clone();
+        tokio::task::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_secs(timeout)).await;
+            event_w
+                .send(Event::Timeout)
+                // event_w is never closed, so this can never fail
+                .unwrap();
+        });
+    }
+    let mut timed_out = false;
+    let mut exit_status = None;
+    let mut control = control::Control::new(prefix);
+    // the screen as it currently looks on the terminal, so that it can be
+    // restored after showing a message, and recorded again after pausing
+    let mut parser = vt100::Parser::new(size.0, size.1, 0);
+    let mut showing_message = false;
+    let mut clock = control::Clock::new(start);
+    let mut stopped = false;
+    let mut bookmark_count = 0;
     loop {
+        let event = if redactor
+            .as_ref()
+            .is_some_and(ttyrec_bin::redact::Redactor::has_pending)
+        {
+            tokio::time::timeout(REDACT_FLUSH_DELAY, event_r.recv())
+                .await
+                .unwrap_or(Some(Event::FlushRedactor))
+        } else {
+            event_r.recv().await
+        };
         // XXX unwrap
-        match event_r.recv().await.unwrap() {
+        match event.unwrap() {
             Event::Key(key) => {
-                let key = key?;
-                if let Some(key) = key {
-                    input_w.send(key.into_bytes()).unwrap();
-                } else {
+                let Some(key) = key? else {
                     break;
+                };
+                if showing_message {
+                    stdout.write_all(&hide_message(parser.screen())).await?;
+                    stdout.flush().await?;
+                    showing_message = false;
+                }
+                let paused = clock.is_paused();
+                let message = match control.key(key.into_bytes()) {
+                    control::Action::Send(bytes) => {
+                        if let Some(keys_writer) = &mut keys_writer {
+                            if !paused {
+                                record_key(
+                                    clock.now(),
+                                    &bytes,
+                                    keys_writer,
+                                    keys_redactor.as_mut(),
+                                    &pty_fd,
+                                )
+                                .await?;
+                            }
+                        }
+                        input_w.send(bytes).unwrap();
+                        None
+                    }
+                    control::Action::TogglePause if stopped => {
+                        Some("recording has been stopped".to_string())
+                    }
+                    control::Action::TogglePause => {
+                        if paused {
+                            let time = clock.elapsed();
+                            let duration = clock.resume();
+                            // written now, so that the pause isn't lost if
+                            // ttyrec doesn't exit cleanly
+                            metadata.pauses.push(
+                                ttyrec_bin::metadata::Pause {
+                                    time: time.as_secs_f64(),
+                                    duration: duration.as_secs_f64(),
+                                },
+                            );
+                            if let Some(metadata_path) = &metadata_path {
+                                metadata.write(metadata_path).await?;
+                            }
+                            // the screen may have changed while we weren't
+                            // recording, so record it as it is now
+                            let mut data = ttyrec_bin::resize::sequence(
+                                parser.screen().size(),
+                            );
+                            data.extend(parser.screen().state_formatted());
+                            record_output(
+                                clock.now(),
+                                &data,
+                                writer.as_mut(),
+                                &mut broadcaster,
+                                redactor.as_mut(),
+                                redaction_log.as_mut(),
+                            )
+                            .await?;
+                            Some("recording resumed".to_string())
+                        } else {
+                            flush_redactor(
+                                writer.as_mut(),
+                                &mut broadcaster,
+                                redactor.as_mut(),
+                                redaction_log.as_mut(),
+                            )
+                            .await?;
+                            clock.pause();
+                            Some("recording paused".to_string())
+                        }
+                    }
+                    control::Action::Bookmark(name) => {
+                        bookmark_count += 1;
+                        let name = if name.is_empty() {
+                            format!("bookmark {bookmark_count}")
+                        } else {
+                            name
+                        };
+                        let time = clock.elapsed();
+                        ttyrec_bin::bookmarks::append(
+                            &bookmarks_path,
+                            &ttyrec_bin::bookmarks::Bookmark {
+                                time: time.as_secs_f64(),
+                                name: name.clone(),
+                            },
+                        )
+                        .await?;
+                        Some(format!("added bookmark {name:?}"))
+                    }
+                    control::Action::Status => {
+                        let time = clock.elapsed();
+                        Some(format!(
+                            "recording to {} ({:.1}s recorded{})",
+                            std::path::Path::new(&file).display(),
+                            time.as_secs_f64(),
+                            if stopped {
+                                ", stopped"
+                            } else if paused {
+                                ", paused"
+                            } else {
+                                ""
+                            }
+                        ))
+                    }
+                    control::Action::Stop => {
+                        if !paused {
+                            flush_redactor(
+                                writer.as_mut(),
+                                &mut broadcaster,
+                                redactor.as_mut(),
+                                redaction_log.as_mut(),
+                            )
+                            .await?;
+                            clock.pause();
+                        }
+                        // the command may keep running for a long time (or
+                        // ttyrec may be killed before it exits), so
+                        // everything is written out now rather than when
+                        // it exits
+                        if let Some(writer) = writer.take() {
+                            finish_recording(
+                                writer,
+                                keys_writer.take(),
+                                keys_redactor.take(),
+                                &mut metadata,
+                                metadata_path.as_deref(),
+                                None,
+                            )
+                            .await?;
+                        }
+                        stopped = true;
+                        Some(
+                            "recording stopped (the command is still \
+                            running)"
+                                .to_string(),
+                        )
+                    }
+                    control::Action::Kill => {
+                        if let Some(pid) = child_pid {
+                            // the child is the leader of its own session,
+                            // so this also kills anything it started in
+                            // the foreground
+                            // SAFETY: kill has no memory safety requirements
+                            unsafe { libc::kill(-pid, libc::SIGKILL) };
+                        }
+                        // the recording ends once the exit status comes in
+                        None
+                    }
+                    control::Action::Help => Some(control::HELP.to_string()),
+                    control::Action::Prompt(name) => {
+                        Some(format!("bookmark name: {name}"))
+                    }
+                    control::Action::Cancel | control::Action::None => None,
+                };
+                if let Some(message) = message {
+                    stdout
+                        .write_all(&control::message(
+                            parser.screen().size(),
+                            &message,
+                        ))
+                        .await?;
+                    stdout.flush().await?;
+                    showing_message = true;
                 }
             }
+            Event::Input(bytes) => {
+                if let Some(keys_writer) = &mut keys_writer {
+                    if !clock.is_paused() {
+                        record_key(
+                            clock.now(),
+                            &bytes,
+                            keys_writer,
+                            keys_redactor.as_mut(),
+                            &pty_fd,
+                        )
+                        .await?;
+                    }
+                }
+                input_w.send(bytes).unwrap();
+            }
             Event::Stdout(bytes) => match bytes {
                 Ok(bytes) => {
-                    writer.frame(&bytes).await?;
-                    stdout.write_all(&bytes).await?;
-                    stdout.flush().await?;
+                    parser.process(&bytes);
+                    if !clock.is_paused() {
+                        record_output(
+                            clock.now(),
+                            &bytes,
+                            writer.as_mut(),
+                            &mut broadcaster,
+                            redactor.as_mut(),
+                            redaction_log.as_mut(),
+                        )
+                        .await?;
+                    }
+                    if echo {
+                        stdout.write_all(&bytes).await?;
+                        stdout.flush().await?;
+                    }
+                    if let Some(driver_w) = &driver_w {
+                        // this fails once the script is done, which is fine
+                        let _ = driver_w.send(bytes.to_vec());
+                    }
                 }
                 Err(e) => {
                     anyhow::bail!("failed to read from child process: {}", e);
                 }
             },
             Event::Resize((h, w)) => {
+                parser.screen_mut().set_size(h, w);
+                let data = ttyrec_bin::resize::sequence((h, w));
+                // resizes while paused are recorded when recording resumes
+                if !clock.is_paused() {
+                    // held back output came before the resize
+                    flush_redactor(
+                        writer.as_mut(),
+                        &mut broadcaster,
+                        redactor.as_mut(),
+                        redaction_log.as_mut(),
+                    )
+                    .await?;
+                    let now = clock.now();
+                    if let Some(writer) = &mut writer {
+                        writer.frame_at(now, &data).await?;
+                    }
+                    if let Some(redactor) = &mut redactor {
+                        redactor.skip(data.len());
+                    }
+                    if let Some(broadcaster) = &mut broadcaster {
+                        broadcaster.frame(now, &data);
+                    }
+                }
+                if let Some(driver_w) = &driver_w {
+                    // this fails once the script is done, which is fine
+                    let _ = driver_w.send(data);
+                }
                 resize_w.send((h, w)).unwrap();
             }
+            Event::Watcher(watcher) => {
+                if let Some(broadcaster) = &mut broadcaster {
+                    broadcaster.watch(watcher);
+                }
+            }
+            Event::FlushRedactor => {
+                flush_redactor(
+                    writer.as_mut(),
+                    &mut broadcaster,
+                    redactor.as_mut(),
+                    redaction_log.as_mut(),
+                )
+                .await?;
+            }
             Event::Error(e) => {
                 return Err(e);
             }
+            Event::Timeout => {
+                timed_out = true;
+                break;
+            }
+            Event::Exit(status) => {
+                exit_status = status;
+                break;
+            }
             Event::Quit => break,
         }
     }
 
+    flush_redactor(
+        writer.as_mut(),
+        &mut broadcaster,
+        redactor.as_mut(),
+        redaction_log.as_mut(),
+    )
+    .await?;
+
+    let exit_status = exit_status.map(Into::into);
+    // if the recording was stopped, it was already finished then
+    if let Some(writer) = writer {
+        finish_recording(
+            writer,
+            keys_writer,
+            keys_redactor,
+            &mut metadata,
+            metadata_path.as_deref(),
+            exit_status,
+        )
+        .await?;
+    }
+
+    if timed_out {
+        anyhow::bail!("timed out waiting for the command to exit");
+    }
+
+    Ok(Some(Summary {
+        file,
+        duration: clock.elapsed(),
+        exit_status,
+    }))
+}
+
+// what happened during the recording, for reporting once it is done
+struct Summary {
+    file: std::ffi::OsString,
+    duration: std::time::Duration,
+    exit_status: Option<ttyrec_bin::metadata::ExitStatus>,
+}
+
+impl Summary {
+    fn exit_code(&self) -> i32 {
+        // the recording can also be stopped by --script or by closing the
+        // input, which isn't a failure
+        self.exit_status
+            .map_or(0, ttyrec_bin::metadata::ExitStatus::code)
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "recorded {:.1}s to {}",
+            self.duration.as_secs_f64(),
+            std::path::Path::new(&self.file).display()
+        )?;
+        if let Some(exit_status) = self.exit_status {
+            write!(f, " (command {exit_status})")?;
+        }
+        Ok(())
+    }
+}
+
+// writes out the end of the recording and of the keys file, and the
+// metadata with how the command exited (or nothing, if it is still
+// running)
+async fn finish_recording(
+    writer: ttyrec_bin::format::Writer,
+    keys_writer: Option<ttyrec_bin::keys::Writer>,
+    keys_redactor: Option<ttyrec_bin::redact::Redactor>,
+    metadata: &mut ttyrec_bin::metadata::Metadata,
+    metadata_path: Option<&std::path::Path>,
+    exit_status: Option<ttyrec_bin::metadata::ExitStatus>,
+) -> anyhow::Result<()> {
+    writer.finish().await?;
+    if let Some(mut keys_writer) = keys_writer {
+        let outputs = keys_redactor.map(|mut redactor| redactor.flush());
+        for output in outputs.into_iter().flatten() {
+            keys_writer.key_at(output.time, &output.data).await?;
+        }
+        keys_writer.finish().await?;
+    }
+    if let Some(metadata_path) = metadata_path {
+        metadata.exit_status = exit_status;
+        metadata.write(metadata_path).await?;
+    }
+    Ok(())
+}
+
+// `writer` is only missing once the recording has been stopped, in which
+// case nothing more is recorded
+async fn record_output(
+    time: std::time::Instant,
+    data: &[u8],
+    writer: Option<&mut ttyrec_bin::format::Writer>,
+    broadcaster: &mut Option<ttyrec_bin::broadcast::Broadcaster>,
+    redactor: Option<&mut ttyrec_bin::redact::Redactor>,
+    mut redaction_log: Option<&mut tokio::fs::File>,
+) -> anyhow::Result<()> {
+    let Some(writer) = writer else {
+        return Ok(());
+    };
+    if let Some(redactor) = redactor {
+        for output in redactor.push(time, data) {
+            record_redacted(
+                output,
+                writer,
+                broadcaster,
+                redaction_log.as_deref_mut(),
+            )
+            .await?;
+        }
+    } else {
+        writer.frame_at(time, data).await?;
+        if let Some(broadcaster) = broadcaster {
+            broadcaster.frame(time, data);
+        }
+    }
+    Ok(())
+}
+
+async fn flush_redactor(
+    writer: Option<&mut ttyrec_bin::format::Writer>,
+    broadcaster: &mut Option<ttyrec_bin::broadcast::Broadcaster>,
+    redactor: Option<&mut ttyrec_bin::redact::Redactor>,
+    mut redaction_log: Option<&mut tokio::fs::File>,
+) -> anyhow::Result<()> {
+    let Some(writer) = writer else {
+        return Ok(());
+    };
+    let outputs = redactor.map(ttyrec_bin::redact::Redactor::flush);
+    for output in outputs.into_iter().flatten() {
+        record_redacted(
+            output,
+            writer,
+            broadcaster,
+            redaction_log.as_deref_mut(),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+// records keystrokes typed at `time`, unless they were typed at a password
+// prompt
+async fn record_key(
+    time: std::time::Instant,
+    bytes: &[u8],
+    keys_writer: &mut ttyrec_bin::keys::Writer,
+    redactor: Option<&mut ttyrec_bin::redact::Redactor>,
+    pty: &std::os::fd::OwnedFd,
+) -> anyhow::Result<()> {
+    if reading_password(pty) {
+        return Ok(());
+    }
+    let Some(redactor) = redactor else {
+        return keys_writer.key_at(time, bytes).await;
+    };
+    let mut outputs = redactor.push(time, bytes);
+    // typed lines end with a carriage return rather than a newline, so
+    // there is no need to wait for more input after one
+    if bytes.contains(&b'\r') {
+        outputs.extend(redactor.flush());
+    }
+    for output in outputs {
+        keys_writer.key_at(output.time, &output.data).await?;
+    }
+    Ok(())
+}
+
+// password prompts turn off echo while leaving the terminal in line mode
+// (programs which turn off echo to draw their own input, like shells and
+// editors, also turn off line mode)
+fn reading_password(pty: &std::os::fd::OwnedFd) -> bool {
+    let mut termios = std::mem::MaybeUninit::uninit();
+    // SAFETY: the fd is valid for as long as pty is borrowed, and termios
+    // is only read after tcgetattr has filled it in
+    let termios = unsafe {
+        if libc::tcgetattr(pty.as_raw_fd(), termios.as_mut_ptr()) != 0 {
+            return false;
+        }
+        termios.assume_init()
+    };
+    termios.c_lflag & libc::ECHO == 0 && termios.c_lflag & libc::ICANON != 0
+}
+
+// returns the bytes to draw over a message shown by control::message,
+// restoring the last row of the screen
+fn hide_message(screen: &vt100::Screen) -> Vec<u8> {
+    let (rows, cols) = screen.size();
+    let mut data = format!("\x1b7\x1b[{rows}H\x1b[m\x1b[2K").into_bytes();
+    data.extend(screen.rows_formatted(0, cols).last().unwrap_or_default());
+    data.extend(b"\x1b8");
+    data
+}
+
+async fn record_redacted(
+    output: ttyrec_bin::redact::Output,
+    writer: &mut ttyrec_bin::format::Writer,
+    broadcaster: &mut Option<ttyrec_bin::broadcast::Broadcaster>,
+    redaction_log: Option<&mut tokio::fs::File>,
+) -> anyhow::Result<()> {
+    writer.frame_at(output.time, &output.data).await?;
+    if let Some(broadcaster) = broadcaster {
+        broadcaster.frame(output.time, &output.data);
+    }
+    if let Some(redaction_log) = redaction_log {
+        for redaction in &output.redactions {
+            let mut line = serde_json::to_vec(redaction)?;
+            line.push(b'\n');
+            redaction_log.write_all(&line).await?;
+        }
+        redaction_log.flush().await?;
+    }
     Ok(())
 }
 
 fn main() {
     let opt = Opt::parse();
+    let quiet = opt.quiet;
     match async_main(opt) {
-        Ok(_) => (),
+        Ok(Some(summary)) => {
+            if !quiet {
+                eprintln!("ttyrec: {summary}");
+            }
+            std::process::exit(summary.exit_code());
+        }
+        Ok(None) => {}
         Err(e) => {
             eprintln!("ttyrec: {e}");
             std::process::exit(1);
         }
-    };
+    }
 }
@@ -0,0 +1,56 @@
+//! Named points in a recording.
+//!
+//! Bookmarks are stored in a separate file next to the recording (see
+//! [`path`]), with one JSON object per line. Bookmarks can be added both
+//! while recording and while playing a recording back, so the file is
+//! only ever appended to.
+
+use tokio::io::AsyncWriteExt as _;
+
+/// Returns the path of the bookmark file for the recording at `path`.
+pub fn path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(".bookmarks");
+    path.into()
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Bookmark {
+    /// Seconds since the start of the recording.
+    pub time: f64,
+    pub name: String,
+}
+
+/// Adds a bookmark to the bookmark file at `path`, creating it if it
+/// doesn't exist yet.
+pub async fn append(
+    path: &std::path::Path,
+    bookmark: &Bookmark,
+) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(bookmark)?;
+    line.push(b'\n');
+    let mut fh = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    fh.write_all(&line).await?;
+    fh.flush().await?;
+    Ok(())
+}
+
+/// Reads every bookmark from the bookmark file at `path`, sorted by time.
+pub async fn load(path: &std::path::Path) -> anyhow::Result<Vec<Bookmark>> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    let mut bookmarks = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| anyhow::anyhow!("line {}: {e}", i + 1))
+        })
+        .collect::<anyhow::Result<Vec<Bookmark>>>()?;
+    bookmarks.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(bookmarks)
+}
@@ -0,0 +1,216 @@
+//! Live streaming of recordings to remote watchers.
+//!
+//! Watchers receive a plain ttyrec stream: a first frame which redraws the
+//! current state of the screen, followed by each frame as it is recorded.
+//! This means that anything which can read a ttyrec file (including just
+//! saving the stream to disk) can also watch a broadcast.
+
+use std::net::ToSocketAddrs as _;
+use std::os::unix::fs::FileTypeExt as _;
+use tokio::io::AsyncWriteExt as _;
+
+// frames which haven't been sent to a watcher yet are buffered up to this
+// limit, after which the watcher is disconnected
+const BACKLOG: usize = 4096;
+
+/// Where to listen for or connect to a broadcast.
+///
+/// Anything which looks like `HOST:PORT` (like `127.0.0.1:8022` or
+/// `localhost:8022`) is treated as a TCP address, and anything else is
+/// treated as the path to a Unix socket. A `unix:` prefix forces the rest
+/// to be treated as a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Tcp(std::net::SocketAddr),
+    Unix(std::path::PathBuf),
+}
+
+impl std::str::FromStr for Address {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            return Ok(Self::Unix(path.into()));
+        }
+        if let Ok(addr) = s.parse() {
+            return Ok(Self::Tcp(addr));
+        }
+        let is_host_port = !s.contains('/')
+            && s.rsplit_once(':').is_some_and(|(host, port)| {
+                !host.is_empty() && port.parse::<u16>().is_ok()
+            });
+        if !is_host_port {
+            return Ok(Self::Unix(s.into()));
+        }
+        s.to_socket_addrs()?.next().map(Self::Tcp).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{s}: no addresses found"),
+            )
+        })
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+pub type Stream = std::pin::Pin<Box<dyn tokio::io::AsyncRead + Send>>;
+
+/// A connection to a single watcher.
+pub struct Watcher(std::pin::Pin<Box<dyn tokio::io::AsyncWrite + Send>>);
+
+impl std::fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watcher").finish_non_exhaustive()
+    }
+}
+
+/// Connects to a broadcast at `addr`, returning the ttyrec stream being
+/// broadcast.
+pub async fn connect(addr: &Address) -> std::io::Result<Stream> {
+    Ok(match addr {
+        Address::Tcp(addr) => {
+            Box::pin(tokio::net::TcpStream::connect(addr).await?)
+        }
+        Address::Unix(path) => {
+            Box::pin(tokio::net::UnixStream::connect(path).await?)
+        }
+    })
+}
+
+pub enum Listener {
+    Tcp(tokio::net::TcpListener),
+    Unix(tokio::net::UnixListener, std::path::PathBuf),
+}
+
+impl Listener {
+    pub async fn bind(addr: &Address) -> std::io::Result<Self> {
+        Ok(match addr {
+            Address::Tcp(addr) => {
+                Self::Tcp(tokio::net::TcpListener::bind(addr).await?)
+            }
+            Address::Unix(path) => {
+                // a socket left behind by a previous process which didn't
+                // exit cleanly would otherwise make this fail (connecting
+                // to anything which isn't a socket is also refused, so
+                // check that it really is one before removing it, and let
+                // the bind fail otherwise)
+                if let Err(e) = tokio::net::UnixStream::connect(path).await {
+                    if e.kind() == std::io::ErrorKind::ConnectionRefused
+                        && std::fs::symlink_metadata(path)?
+                            .file_type()
+                            .is_socket()
+                    {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+                Self::Unix(
+                    tokio::net::UnixListener::bind(path)?,
+                    path.clone(),
+                )
+            }
+        })
+    }
+
+    /// Waits for a new watcher to connect.
+    pub async fn accept(&self) -> std::io::Result<Watcher> {
+        Ok(Watcher(match self {
+            Self::Tcp(listener) => {
+                let (stream, _) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+                Box::pin(stream)
+            }
+            Self::Unix(listener, _) => {
+                let (stream, _) = listener.accept().await?;
+                Box::pin(stream)
+            }
+        }))
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Self::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Tracks the state of the recorded terminal, and sends each recorded frame
+/// to every connected watcher.
+pub struct Broadcaster {
+    parser: vt100::Parser,
+    creator: ttyrec::Creator,
+    frames: tokio::sync::broadcast::Sender<std::sync::Arc<Vec<u8>>>,
+    // the time of the most recent frame, which is what new watchers'
+    // snapshots are sent at. the recording's clock doesn't advance while
+    // it is paused, so using the current time here could put the snapshot
+    // after frames which are sent later.
+    last_time: Option<std::time::Instant>,
+}
+
+impl Broadcaster {
+    pub fn new((rows, cols): (u16, u16)) -> Self {
+        let (frames, _) = tokio::sync::broadcast::channel(BACKLOG);
+        Self {
+            parser: vt100::Parser::new(rows, cols, 0),
+            creator: ttyrec::Creator::new(),
+            frames,
+            last_time: None,
+        }
+    }
+
+    /// Sends a frame recorded at `time` to all watchers.
+    pub fn frame(&mut self, time: std::time::Instant, data: &[u8]) {
+        crate::resize::process(&mut self.parser, data);
+        self.last_time = Some(time);
+        if self.frames.receiver_count() == 0 {
+            // still need to call this so that the first frame sets the
+            // base time
+            self.creator.frame_at(time, b"");
+            return;
+        }
+        if let Ok(bytes) = self.creator.frame_at(time, data).try_into() {
+            // this can only fail if every watcher disconnected since the
+            // check above, which is fine
+            let _ = self.frames.send(std::sync::Arc::new(bytes));
+        }
+    }
+
+    /// Starts sending frames to a newly connected watcher, beginning with
+    /// a snapshot of the current screen.
+    pub fn watch(&mut self, watcher: Watcher) {
+        let Watcher(mut sink) = watcher;
+        let screen = self.parser.screen();
+        let mut snapshot = crate::resize::sequence(screen.size());
+        snapshot.extend(screen.state_formatted());
+        let snapshot: Result<Vec<u8>, _> = self
+            .creator
+            .frame_at(
+                self.last_time.unwrap_or_else(std::time::Instant::now),
+                &snapshot,
+            )
+            .try_into();
+        let Ok(snapshot) = snapshot else { return };
+        let mut frames = self.frames.subscribe();
+        tokio::task::spawn(async move {
+            if sink.write_all(&snapshot).await.is_err() {
+                return;
+            }
+            // this stops both when the recording ends and when the watcher
+            // falls too far behind
+            while let Ok(frame) = frames.recv().await {
+                if sink.write_all(&frame).await.is_err() {
+                    return;
+                }
+            }
+            let _ = sink.shutdown().await;
+        });
+    }
+}
@@ -0,0 +1,328 @@
+//! Reading and writing compressed recordings.
+
+use tokio::io::AsyncReadExt as _;
+use tokio::io::AsyncSeekExt as _;
+use tokio::io::AsyncWrite as _;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    const MAGIC: &'static [(Self, &'static [u8])] = &[
+        (Self::Gzip, b"\x1f\x8b"),
+        (Self::Bzip2, b"BZh"),
+        (Self::Xz, b"\xfd7zXZ\x00"),
+        (Self::Zstd, b"\x28\xb5\x2f\xfd"),
+    ];
+
+    /// Returns the compression format used by data starting with `magic`,
+    /// if any.
+    pub fn from_magic(magic: &[u8]) -> Option<Self> {
+        Self::MAGIC
+            .iter()
+            .find(|(_, prefix)| magic.starts_with(prefix))
+            .map(|(compression, _)| *compression)
+    }
+
+    /// Returns the compression format used by `fh`, leaving the file
+    /// positioned at the start.
+    pub async fn detect(
+        fh: &mut tokio::fs::File,
+    ) -> std::io::Result<Option<Self>> {
+        let mut magic = vec![];
+        (&mut *fh).take(8).read_to_end(&mut magic).await?;
+        fh.rewind().await?;
+        Ok(Self::from_magic(&magic))
+    }
+
+    /// Wraps `input` so that reads return decompressed data.
+    pub fn decoder<R: tokio::io::AsyncRead + Send + 'static>(
+        self,
+        input: R,
+    ) -> std::pin::Pin<Box<dyn tokio::io::AsyncRead + Send>> {
+        use async_compression::tokio::bufread;
+
+        let input = tokio::io::BufReader::new(input);
+        // compressed files are commonly concatenated (for instance, by
+        // appending to a file with `gzip -c >>`)
+        match self {
+            Self::Gzip => {
+                let mut decoder = bufread::GzipDecoder::new(input);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+            Self::Bzip2 => {
+                let mut decoder = bufread::BzDecoder::new(input);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+            Self::Xz => {
+                let mut decoder = bufread::XzDecoder::new(input);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+            Self::Zstd => {
+                let mut decoder = bufread::ZstdDecoder::new(input);
+                decoder.multiple_members(true);
+                Box::pin(decoder)
+            }
+        }
+    }
+
+    /// Wraps `output` so that written data is compressed. Data is only
+    /// guaranteed to be readable after the encoder is flushed, and the
+    /// encoder must be shut down to write out the end of the stream.
+    ///
+    /// Everything written before the last flush can be decompressed even if
+    /// the encoder is never shut down (for instance, if the program
+    /// crashes), except with bzip2, where the most recent few seconds of
+    /// data may not be readable until the encoder is shut down.
+    pub fn encoder<W: tokio::io::AsyncWrite + Send + 'static>(
+        self,
+        output: W,
+    ) -> std::pin::Pin<Box<dyn tokio::io::AsyncWrite + Send>> {
+        use async_compression::tokio::write;
+
+        match self {
+            Self::Gzip => Box::pin(write::GzipEncoder::new(output)),
+            Self::Bzip2 => {
+                Box::pin(Bzip2Encoder::new(output, BZIP2_STREAM_INTERVAL))
+            }
+            Self::Xz => Box::pin(write::XzEncoder::new(output)),
+            Self::Zstd => Box::pin(write::ZstdEncoder::new(output)),
+        }
+    }
+}
+
+// bzip2 can't write out the last few bits of a block until the next block
+// starts, so the most recently flushed data can't be decompressed until
+// more is written. flushing a bzip2 stream also ends the current block,
+// which (for the small writes made when recording) costs far more than the
+// data being written. so instead, flushes are ignored until the stream has
+// been going for this long, at which point the stream is ended and the
+// next write starts a new one (which decoders treat the same as a single
+// stream).
+const BZIP2_STREAM_INTERVAL: std::time::Duration =
+    std::time::Duration::from_secs(10);
+
+struct Bzip2Encoder {
+    state: Bzip2State,
+    // how long a stream lasts before a flush ends it
+    interval: std::time::Duration,
+}
+
+enum Bzip2State {
+    // along with when the stream was started
+    Stream(
+        async_compression::tokio::write::BzEncoder<KeepOpen>,
+        std::time::Instant,
+    ),
+    Ended(KeepOpen),
+    // only while switching between the other states
+    Switching,
+}
+
+impl Bzip2Encoder {
+    fn new<W: tokio::io::AsyncWrite + Send + 'static>(
+        output: W,
+        interval: std::time::Duration,
+    ) -> Self {
+        Self {
+            state: Bzip2State::Ended(KeepOpen(Box::pin(output))),
+            interval,
+        }
+    }
+
+    fn poll_end_stream(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        if let Bzip2State::Stream(encoder, _) = &mut self.state {
+            std::task::ready!(std::pin::Pin::new(encoder).poll_shutdown(cx))?;
+            let Bzip2State::Stream(encoder, _) =
+                std::mem::replace(&mut self.state, Bzip2State::Switching)
+            else {
+                unreachable!()
+            };
+            self.state = Bzip2State::Ended(encoder.into_inner());
+        }
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn output(&mut self) -> &mut KeepOpen {
+        match &mut self.state {
+            Bzip2State::Stream(encoder, _) => encoder.get_mut(),
+            Bzip2State::Ended(output) => output,
+            Bzip2State::Switching => unreachable!(),
+        }
+    }
+}
+
+impl tokio::io::AsyncWrite for Bzip2Encoder {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if let Bzip2State::Ended(_) = this.state {
+            let Bzip2State::Ended(output) =
+                std::mem::replace(&mut this.state, Bzip2State::Switching)
+            else {
+                unreachable!()
+            };
+            this.state = Bzip2State::Stream(
+                async_compression::tokio::write::BzEncoder::new(output),
+                std::time::Instant::now(),
+            );
+        }
+        let Bzip2State::Stream(encoder, _) = &mut this.state else {
+            unreachable!()
+        };
+        std::pin::Pin::new(encoder).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let interval = this.interval;
+        if matches!(
+            this.state,
+            Bzip2State::Stream(_, started) if started.elapsed() >= interval
+        ) {
+            std::task::ready!(this.poll_end_stream(cx))?;
+        }
+        this.output().0.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        std::task::ready!(this.poll_end_stream(cx))?;
+        this.output().0.as_mut().poll_shutdown(cx)
+    }
+}
+
+// shutting down an encoder also shuts down the writer it wraps, which
+// shouldn't happen when only the compressed stream is ending
+struct KeepOpen(std::pin::Pin<Box<dyn tokio::io::AsyncWrite + Send>>);
+
+impl tokio::io::AsyncWrite for KeepOpen {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        self.0.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        self.0.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        self.0.as_mut().poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt as _;
+    use tokio::io::AsyncWriteExt as _;
+
+    #[tokio::test]
+    async fn flushed_prefix_is_readable() {
+        for compression in [
+            super::Compression::Gzip,
+            super::Compression::Bzip2,
+            super::Compression::Xz,
+            super::Compression::Zstd,
+        ] {
+            let path = std::env::temp_dir().join(format!(
+                "ttyrec-bin-test-{}-{compression:?}",
+                std::process::id()
+            ));
+            let fh = tokio::fs::File::create(&path).await.unwrap();
+            let mut encoder = if compression == super::Compression::Bzip2 {
+                // bzip2 streams normally only end every few seconds
+                Box::pin(super::Bzip2Encoder::new(
+                    fh,
+                    std::time::Duration::ZERO,
+                ))
+            } else {
+                compression.encoder(fh)
+            };
+            encoder.write_all(b"first frame").await.unwrap();
+            encoder.flush().await.unwrap();
+            encoder.write_all(b"second frame").await.unwrap();
+            encoder.flush().await.unwrap();
+            // simulate a crash by never shutting down the encoder
+            std::mem::forget(encoder);
+
+            let data = std::fs::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(
+                super::Compression::from_magic(&data),
+                Some(compression)
+            );
+            let mut decoder = compression.decoder(std::io::Cursor::new(data));
+            let mut decoded: Vec<u8> = vec![];
+            let mut buf = [0; 1024];
+            // the end of the stream is missing, so reading eventually fails
+            while let Ok(n) = decoder.read(&mut buf).await {
+                if n == 0 {
+                    break;
+                }
+                decoded.extend(&buf[..n]);
+            }
+            assert_eq!(
+                decoded, b"first framesecond frame",
+                "{compression:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn bzip2_flushes_are_cheap() {
+        let path = std::env::temp_dir()
+            .join(format!("ttyrec-bin-test-{}-flushes", std::process::id()));
+        let fh = tokio::fs::File::create(&path).await.unwrap();
+        let mut encoder = super::Compression::Bzip2.encoder(fh);
+        let frame = b"\x1b[12;34Hsome typical output";
+        for _ in 0..1000 {
+            encoder.write_all(frame).await.unwrap();
+            encoder.flush().await.unwrap();
+        }
+        encoder.shutdown().await.unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(data.len() < frame.len() * 10, "{}", data.len());
+        let mut decoded = vec![];
+        super::Compression::Bzip2
+            .decoder(std::io::Cursor::new(data))
+            .read_to_end(&mut decoded)
+            .await
+            .unwrap();
+        assert_eq!(decoded, frame.repeat(1000));
+    }
+}
@@ -0,0 +1,262 @@
+//! Defaults for `ttyrec` and `ttyplay`, read from a config file.
+//!
+//! The config file is TOML, with a `[ttyrec]` table and a `[ttyplay]`
+//! table, and lives at `config.toml` in the config directory (see [`dir`])
+//! unless `--config` is given. Every setting is optional, and options given
+//! on the command line always take precedence over the config file.
+
+/// Returns the directory which the config file (and other configuration,
+/// like `ttyplay`'s keymap) is read from. This follows the XDG base
+/// directory spec on Linux, so it is usually `~/.config/ttyrec-bin`.
+pub fn dir() -> Option<std::path::PathBuf> {
+    directories::ProjectDirs::from("", "", "ttyrec-bin")
+        .map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// Returns the path of the config file which is used if `--config` isn't
+/// given.
+pub fn default_path() -> Option<std::path::PathBuf> {
+    dir().map(|dir| dir.join("config.toml"))
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // sections left at their defaults are skipped, so that each program's
+    // --print-config only shows its own settings
+    #[serde(skip_serializing_if = "is_default")]
+    pub ttyrec: Ttyrec,
+    #[serde(skip_serializing_if = "is_default")]
+    pub ttyplay: Ttyplay,
+}
+
+impl Config {
+    /// Reads the config file at `path`, or at [`default_path`] if `path`
+    /// is `None`. The default config file doesn't need to exist, but a
+    /// config file which was asked for explicitly does.
+    pub async fn load(
+        path: Option<&std::path::Path>,
+    ) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let contents = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        let mut config: Self = toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        config.ttyrec.directory =
+            config.ttyrec.directory.as_deref().map(expand_home);
+        // a relative keymap path is relative to the config file, not to
+        // wherever ttyplay happens to be run from
+        config.ttyplay.keymap =
+            config.ttyplay.keymap.as_deref().map(|keymap| {
+                let keymap = expand_home(keymap);
+                path.parent()
+                    .map_or_else(|| keymap.clone(), |dir| dir.join(&keymap))
+            });
+        Ok(config)
+    }
+
+    /// Returns the config as TOML, for `--print-config`.
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ttyrec {
+    /// The directory to save recordings in when `--file` isn't given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory: Option<std::path::PathBuf>,
+    /// The name to save recordings as when `--file` isn't given, where
+    /// `{date}` and `{time}` are replaced by the (UTC) date and time the
+    /// recording started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compress: Option<crate::compression::Compression>,
+}
+
+impl Ttyrec {
+    /// Returns the path to save a recording which is starting at `time`
+    /// (in seconds since the unix epoch) to, when `--file` isn't given.
+    // {date} and {time} are placeholders in the template, not format args
+    #[allow(clippy::literal_string_with_formatting_args)]
+    pub fn file_path(&self, time: u64) -> std::path::PathBuf {
+        let name = self.file.as_deref().unwrap_or("ttyrec");
+        let formatted = crate::metadata::format_utc(time);
+        let mut parts = formatted.split(' ');
+        let date = parts.next().unwrap_or_default();
+        // colons aren't allowed in file names everywhere
+        let time = parts.next().unwrap_or_default().replace(':', "-");
+        let name = name.replace("{date}", date).replace("{time}", &time);
+        self.directory
+            .as_ref()
+            .map_or_else(|| name.clone().into(), |dir| dir.join(&name))
+    }
+}
+
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ttyplay {
+    /// The default for `ttyplay --clamp`, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clamp: Option<u64>,
+    /// The default for `ttyplay --speed`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<u32>,
+    /// The default for `ttyplay --keymap`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keymap: Option<std::path::PathBuf>,
+    pub colors: Colors,
+}
+
+/// The colors used to draw `ttyplay`'s interface.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    /// Text in the status bars, prompts and overlays.
+    pub text: Color,
+    /// The background of the status bars, prompts and overlays, and the
+    /// loaded part of the timeline.
+    pub background: Color,
+    /// The frame count while the recording is loading, and the pause
+    /// symbol.
+    pub highlight: Color,
+    /// Bookmarks on the timeline, and the bookmark name prompt.
+    pub bookmark: Color,
+    /// Search matches on the timeline.
+    pub search: Color,
+    /// The current position on the timeline.
+    pub position: Color,
+    /// The frame count while watching a live recording and caught up with
+    /// it.
+    pub live: Color,
+    /// The frame count while watching a live recording and behind it.
+    pub behind: Color,
+    /// The background of the keys overlay.
+    pub keys: Color,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            text: Color::Idx(0),
+            background: Color::Idx(6),
+            highlight: Color::Idx(1),
+            bookmark: Color::Idx(3),
+            search: Color::Idx(5),
+            position: Color::Idx(15),
+            live: Color::Idx(2),
+            behind: Color::Idx(3),
+            keys: Color::Idx(3),
+        }
+    }
+}
+
+// the names of the first 16 colors, in the same order as their indexes
+const COLOR_NAMES: &[&str] = &[
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "lightgrey",
+    "darkgrey",
+    "lightred",
+    "lightgreen",
+    "lightyellow",
+    "lightblue",
+    "lightmagenta",
+    "lightcyan",
+    "white",
+];
+
+/// A terminal color, written in the config file as a name (like `cyan` or
+/// `lightred`), an index into the 256 color palette, or `#rrggbb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Idx(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl std::str::FromStr for Color {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                anyhow::bail!("expected #rrggbb");
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+            return Ok(Self::Rgb(channel(0)?, channel(2)?, channel(4)?));
+        }
+        if let Some(idx) = COLOR_NAMES.iter().position(|name| *name == s) {
+            // there are only 16 names
+            return Ok(Self::Idx(u8::try_from(idx).unwrap()));
+        }
+        s.parse()
+            .map(Self::Idx)
+            .map_err(|_| anyhow::anyhow!("unknown color {s:?}"))
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Idx(idx) => {
+                if let Some(name) = COLOR_NAMES.get(usize::from(*idx)) {
+                    f.write_str(name)
+                } else {
+                    write!(f, "{idx}")
+                }
+            }
+            Self::Rgb(r, g, b) => write!(f, "#{r:02x}{g:02x}{b:02x}"),
+        }
+    }
+}
+
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+// replaces a leading `~` with the home directory
+fn expand_home(path: &std::path::Path) -> std::path::PathBuf {
+    let Ok(rest) = path.strip_prefix("~") else {
+        return path.to_path_buf();
+    };
+    directories::BaseDirs::new()
+        .map_or_else(|| path.to_path_buf(), |dirs| dirs.home_dir().join(rest))
+}
@@ -0,0 +1,287 @@
+//! Reading and writing recordings in any of the supported file formats.
+
+use tokio::io::AsyncBufReadExt as _;
+use tokio::io::AsyncReadExt as _;
+use tokio::io::AsyncSeekExt as _;
+use tokio::io::AsyncWriteExt as _;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum Format {
+    Ttyrec,
+    Asciicast,
+}
+
+impl Format {
+    /// Determines the format of `input` from the data at the start of it,
+    /// without consuming anything. Returns `None` if `input` is empty.
+    pub async fn detect<T: tokio::io::AsyncRead + std::marker::Unpin>(
+        input: &mut tokio::io::BufReader<T>,
+    ) -> std::io::Result<Option<Self>> {
+        Ok(Self::from_prefix(input.fill_buf().await?))
+    }
+
+    /// Determines the format of the uncompressed file `fh`, leaving the file
+    /// positioned at the start. Returns `None` if the file is empty.
+    pub async fn detect_file(
+        fh: &mut tokio::fs::File,
+    ) -> std::io::Result<Option<Self>> {
+        let mut prefix = vec![];
+        (&mut *fh).take(8).read_to_end(&mut prefix).await?;
+        fh.rewind().await?;
+        Ok(Self::from_prefix(&prefix))
+    }
+
+    fn from_prefix(prefix: &[u8]) -> Option<Self> {
+        if prefix.is_empty() {
+            None
+        } else if crate::asciicast::detect(prefix) {
+            Some(Self::Asciicast)
+        } else {
+            Some(Self::Ttyrec)
+        }
+    }
+}
+
+/// Reads frames from a recording, in the same form as ttyrec frames.
+/// Terminal size changes are returned as frames containing resize sequences
+/// (see [`crate::resize`]).
+pub enum Reader<T: tokio::io::AsyncRead> {
+    Ttyrec(Box<ttyrec::Reader<T>>),
+    Asciicast {
+        reader: crate::asciicast::Reader<T>,
+        header: Option<crate::asciicast::Header>,
+        // input and marker events, if keep_events was called
+        events: Option<Vec<crate::asciicast::Event>>,
+    },
+    Typescript(
+        Box<
+            crate::typescript::Reader<
+                T,
+                std::pin::Pin<Box<dyn tokio::io::AsyncRead + Send>>,
+            >,
+        >,
+    ),
+}
+
+impl<T: tokio::io::AsyncRead + std::marker::Unpin + Send> Reader<T> {
+    pub fn new(input: T, format: Format) -> Self {
+        match format {
+            Format::Ttyrec => {
+                Self::Ttyrec(Box::new(ttyrec::Reader::new(input)))
+            }
+            Format::Asciicast => Self::Asciicast {
+                reader: crate::asciicast::Reader::new(input),
+                header: None,
+                events: None,
+            },
+        }
+    }
+
+    /// Keeps the events in an asciicast recording which don't affect the
+    /// terminal output (input and markers), rather than skipping them, so
+    /// that they can be retrieved with [`Self::take_events`].
+    pub fn keep_events(&mut self) {
+        if let Self::Asciicast { events, .. } = self {
+            *events = Some(vec![]);
+        }
+    }
+
+    /// Returns the events kept since this was last called (see
+    /// [`Self::keep_events`]), which all came before the next frame.
+    pub fn take_events(&mut self) -> Vec<crate::asciicast::Event> {
+        match self {
+            Self::Asciicast {
+                events: Some(events),
+                ..
+            } => std::mem::take(events),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the header of an asciicast recording, once the first frame
+    /// has been read.
+    pub fn asciicast_header(&self) -> Option<&crate::asciicast::Header> {
+        match self {
+            Self::Asciicast { header, .. } => header.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Reads a typescript from `script`, with the separate timing file
+    /// `timing`.
+    pub fn typescript<U: tokio::io::AsyncRead + Send + 'static>(
+        log: T,
+        timing: U,
+    ) -> Self {
+        Self::Typescript(Box::new(crate::typescript::Reader::new(
+            log,
+            Box::pin(timing),
+        )))
+    }
+
+    /// Returns the next frame. Returns `None` if the end of the input was
+    /// reached, in which case this can be called again if more data becomes
+    /// available.
+    pub async fn read_frame(
+        &mut self,
+    ) -> anyhow::Result<Option<ttyrec::Frame>> {
+        match self {
+            Self::Ttyrec(reader) => match reader.read_frame().await {
+                Ok(frame) => Ok(Some(frame)),
+                Err(ttyrec::Error::EOF) => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            Self::Asciicast {
+                reader,
+                header,
+                events,
+            } => {
+                if header.is_none() {
+                    let Some(new_header) = reader.read_header().await? else {
+                        return Ok(None);
+                    };
+                    let size = new_header.size();
+                    *header = Some(new_header);
+                    return Ok(Some(ttyrec::Frame {
+                        time: std::time::Duration::ZERO,
+                        data: crate::resize::sequence(size),
+                    }));
+                }
+                loop {
+                    let Some(event) = reader.read_event().await? else {
+                        return Ok(None);
+                    };
+                    match event {
+                        crate::asciicast::Event::Output(time, data) => {
+                            return Ok(Some(ttyrec::Frame {
+                                time,
+                                data: data.into_bytes(),
+                            }));
+                        }
+                        crate::asciicast::Event::Resize(time, size) => {
+                            return Ok(Some(ttyrec::Frame {
+                                time,
+                                data: crate::resize::sequence(size),
+                            }));
+                        }
+                        // these don't affect the terminal output
+                        crate::asciicast::Event::Input(..)
+                        | crate::asciicast::Event::Marker(..) => {
+                            if let Some(events) = events {
+                                events.push(event);
+                            }
+                        }
+                    }
+                }
+            }
+            Self::Typescript(reader) => reader.read_frame().await,
+        }
+    }
+}
+
+enum Encoder {
+    Ttyrec,
+    Asciicast(crate::asciicast::Encoder),
+}
+
+/// Writes frames to a recording.
+pub struct Writer {
+    output: std::pin::Pin<Box<dyn tokio::io::AsyncWrite + Send>>,
+    creator: ttyrec::Creator,
+    encoder: Encoder,
+    header: Option<Vec<u8>>,
+}
+
+impl Writer {
+    /// Creates a new writer. `header` is only used for formats which store
+    /// information about the recording (currently only asciicast).
+    pub fn new<W: tokio::io::AsyncWrite + Send + 'static>(
+        output: W,
+        format: Format,
+        compression: Option<crate::compression::Compression>,
+        header: &crate::asciicast::Header,
+    ) -> Self {
+        let output = if let Some(compression) = compression {
+            compression.encoder(output)
+        } else {
+            Box::pin(output)
+        };
+        let (encoder, header) = match format {
+            Format::Ttyrec => (Encoder::Ttyrec, None),
+            Format::Asciicast => {
+                let (encoder, header) =
+                    crate::asciicast::Encoder::new(header);
+                (Encoder::Asciicast(encoder), Some(header))
+            }
+        };
+        Self {
+            output,
+            creator: ttyrec::Creator::new(),
+            encoder,
+            header,
+        }
+    }
+
+    /// Writes a frame recorded at `time`, making sure that it is written
+    /// out immediately.
+    pub async fn frame_at(
+        &mut self,
+        time: std::time::Instant,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        let frame = self.creator.frame_at(time, data);
+        self.write_frame(frame).await?;
+        // flushing after every frame costs a bit of compression, but means
+        // that the file can be watched while it is being written, and that
+        // everything up to the last frame is still readable if we crash
+        // (other than the last few seconds of bzip2 recordings, see
+        // Compression::encoder)
+        self.output.flush().await?;
+        Ok(())
+    }
+
+    /// Writes a frame whose time is relative to the start of the recording.
+    pub async fn write_frame(
+        &mut self,
+        frame: ttyrec::Frame,
+    ) -> anyhow::Result<()> {
+        let mut bytes = self.header.take().unwrap_or_default();
+        match &mut self.encoder {
+            Encoder::Ttyrec => {
+                bytes.extend(Vec::<u8>::try_from(frame)?);
+            }
+            Encoder::Asciicast(encoder) => {
+                bytes.extend(encoder.frame(frame.time, &frame.data));
+            }
+        }
+        self.output.write_all(&bytes).await?;
+        Ok(())
+    }
+
+    /// Writes an asciicast event which isn't part of the terminal output
+    /// (input or a marker). Other formats have nowhere to store these, so
+    /// they are dropped.
+    pub async fn write_event(
+        &mut self,
+        event: &crate::asciicast::Event,
+    ) -> anyhow::Result<()> {
+        let Encoder::Asciicast(encoder) = &mut self.encoder else {
+            return Ok(());
+        };
+        let mut bytes = self.header.take().unwrap_or_default();
+        bytes.extend(encoder.event(event));
+        self.output.write_all(&bytes).await?;
+        Ok(())
+    }
+
+    /// Writes out anything still buffered, including the end of the
+    /// compressed stream if the output is compressed.
+    pub async fn finish(mut self) -> anyhow::Result<()> {
+        if let Some(header) = self.header.take() {
+            self.output.write_all(&header).await?;
+        }
+        self.output.shutdown().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,400 @@
+//! Recording keystrokes alongside a recording.
+//!
+//! Keystrokes are stored in a separate file next to the recording (see
+//! [`path`]), as a ttyrec stream where each frame contains the bytes sent
+//! to the terminal for a single key. Frame times share the same starting
+//! point as the recording itself, so the two can be matched up on playback.
+
+use tokio::io::AsyncWriteExt as _;
+
+/// Returns the path of the keystroke file for the recording at `path`.
+pub fn path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(".keys");
+    path.into()
+}
+
+/// Writes keystrokes to a keystroke file.
+pub struct Writer {
+    output: std::pin::Pin<Box<dyn tokio::io::AsyncWrite + Send>>,
+    creator: ttyrec::Creator,
+}
+
+impl Writer {
+    /// Creates a new writer. `start` must be the time of the first frame of
+    /// the recording.
+    pub fn new<W: tokio::io::AsyncWrite + Send + 'static>(
+        output: W,
+        start: std::time::Instant,
+    ) -> Self {
+        let mut creator = ttyrec::Creator::new();
+        creator.frame_at(start, b"");
+        Self {
+            output: Box::pin(output),
+            creator,
+        }
+    }
+
+    /// Writes a keystroke which was pressed at `time`.
+    pub async fn key_at(
+        &mut self,
+        time: std::time::Instant,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        let bytes: Vec<u8> = self.creator.frame_at(time, data).try_into()?;
+        self.output.write_all(&bytes).await?;
+        self.output.flush().await?;
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> anyhow::Result<()> {
+        self.output.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// Reads every keystroke from the keystroke file at `path`.
+pub async fn load(
+    path: &std::path::Path,
+) -> anyhow::Result<Vec<ttyrec::Frame>> {
+    let fh = tokio::fs::File::open(path).await?;
+    let mut reader = ttyrec::Reader::new(fh);
+    let mut keys = vec![];
+    loop {
+        match reader.read_frame().await {
+            Ok(frame) => keys.push(frame),
+            Err(ttyrec::Error::EOF) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(keys)
+}
+
+/// A single keystroke, for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Key {
+    /// A key which types a character.
+    Char(char),
+    /// Any other key (including modifiers), named like `C-c` or `Esc`.
+    Named(String),
+}
+
+/// Splits the bytes sent to a terminal for some keystrokes into the
+/// individual keys. Escape sequences which aren't recognized are dropped.
+pub fn parse(data: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (key, len) = parse_one(rest);
+        keys.extend(key);
+        rest = &rest[len..];
+    }
+    keys
+}
+
+/// Returns the bytes sent to a terminal for the key named `name`, using the
+/// same names as [`parse`] (like `a`, `Enter`, `C-c`, `M-b` or `S-Up`), as
+/// well as `Space`.
+pub fn encode(name: &str) -> Option<Vec<u8>> {
+    let mut modifiers = 0;
+    let mut base = name;
+    // a single character is always the key itself, even if it is `-`
+    while base.chars().nth(1).is_some() {
+        if let Some(rest) = base.strip_prefix("C-") {
+            modifiers |= 4;
+            base = rest;
+        } else if let Some(rest) = base.strip_prefix("M-") {
+            modifiers |= 2;
+            base = rest;
+        } else if let Some(rest) = base.strip_prefix("S-") {
+            modifiers |= 1;
+            base = rest;
+        } else {
+            break;
+        }
+    }
+
+    let mut chars = base.chars();
+    let bytes = if let (Some(c), None) = (chars.next(), chars.next()) {
+        char_bytes(c, modifiers)?
+    } else {
+        match base {
+            "Space" => char_bytes(' ', modifiers)?,
+            "Tab" if modifiers & 1 != 0 => b"\x1b[Z".to_vec(),
+            "Tab" => b"\t".to_vec(),
+            "Enter" => b"\r".to_vec(),
+            "Backspace" => b"\x7f".to_vec(),
+            "Esc" => b"\x1b".to_vec(),
+            _ => return csi_bytes(base, modifiers),
+        }
+    };
+    if modifiers & 2 != 0 {
+        Some([b"\x1b".as_slice(), &bytes].concat())
+    } else {
+        Some(bytes)
+    }
+}
+
+// the bytes for a character key, without the meta modifier
+fn char_bytes(c: char, modifiers: u8) -> Option<Vec<u8>> {
+    let c = if modifiers & 1 != 0 {
+        c.to_ascii_uppercase()
+    } else {
+        c
+    };
+    if modifiers & 4 == 0 {
+        return Some(c.to_string().into_bytes());
+    }
+    let byte = match c.to_ascii_lowercase() {
+        c @ 'a'..='z' => u8::try_from(c).ok()? - 0x60,
+        ' ' | '@' => 0x00,
+        '[' => 0x1b,
+        '\\' => 0x1c,
+        ']' => 0x1d,
+        '^' => 0x1e,
+        '_' => 0x1f,
+        '?' => 0x7f,
+        _ => return None,
+    };
+    Some(vec![byte])
+}
+
+// the bytes for a key which sends an escape sequence, including any
+// modifiers
+fn csi_bytes(name: &str, modifiers: u8) -> Option<Vec<u8>> {
+    let (param, last) = match name {
+        "Up" => ("1", 'A'),
+        "Down" => ("1", 'B'),
+        "Right" => ("1", 'C'),
+        "Left" => ("1", 'D'),
+        "Home" => ("1", 'H'),
+        "End" => ("1", 'F'),
+        "F1" => ("1", 'P'),
+        "F2" => ("1", 'Q'),
+        "F3" => ("1", 'R'),
+        "F4" => ("1", 'S'),
+        "Insert" => ("2", '~'),
+        "Delete" => ("3", '~'),
+        "PgUp" => ("5", '~'),
+        "PgDn" => ("6", '~'),
+        "F5" => ("15", '~'),
+        "F6" => ("17", '~'),
+        "F7" => ("18", '~'),
+        "F8" => ("19", '~'),
+        "F9" => ("20", '~'),
+        "F10" => ("21", '~'),
+        "F11" => ("23", '~'),
+        "F12" => ("24", '~'),
+        _ => return None,
+    };
+    let s = if modifiers != 0 {
+        format!("\x1b[{param};{}{last}", modifiers + 1)
+    } else if last == '~' {
+        format!("\x1b[{param}~")
+    } else if name.starts_with('F') {
+        format!("\x1bO{last}")
+    } else {
+        format!("\x1b[{last}")
+    };
+    Some(s.into_bytes())
+}
+
+// returns the key at the start of `data`, along with the number of bytes
+// it used
+fn parse_one(data: &[u8]) -> (Option<Key>, usize) {
+    match data {
+        [b'\x1b', b'[' | b'O', ..] => {
+            let Some(end) =
+                data[2..].iter().position(|c| (0x40..=0x7e).contains(c))
+            else {
+                return (None, data.len());
+            };
+            let len = end + 3;
+            (
+                csi(&data[2..len - 1], data[len - 1], data[1] == b'O')
+                    .map(Key::Named),
+                len,
+            )
+        }
+        [b'\x1b'] => (Some(Key::Named("Esc".to_string())), 1),
+        [b'\x1b', ..] => {
+            let (key, len) = parse_one(&data[1..]);
+            (
+                key.map(|key| match key {
+                    Key::Char(c) => Key::Named(format!("M-{c}")),
+                    Key::Named(name) => Key::Named(format!("M-{name}")),
+                }),
+                len + 1,
+            )
+        }
+        [c, ..] if *c < 0x20 || *c == 0x7f => {
+            let name = match c {
+                b'\t' => "Tab".to_string(),
+                b'\r' | b'\n' => "Enter".to_string(),
+                b'\x7f' | b'\x08' => "Backspace".to_string(),
+                b'\0' => "C-Space".to_string(),
+                _ => format!("C-{}", char::from(c + 0x60)),
+            };
+            (Some(Key::Named(name)), 1)
+        }
+        _ => {
+            let len = utf8_len(data[0]).min(data.len());
+            let key = std::str::from_utf8(&data[..len])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .map(Key::Char);
+            (key, len)
+        }
+    }
+}
+
+// names the key for a CSI (or SS3, if `ss3` is set) sequence with the given
+// parameters and final byte
+fn csi(params: &[u8], last: u8, ss3: bool) -> Option<String> {
+    let params = std::str::from_utf8(params).ok()?;
+    let mut params = params.split(';');
+    let first = params.next().unwrap_or_default();
+    let modifiers = params
+        .next()
+        .and_then(|m| m.parse::<u8>().ok())
+        .map_or(0, |m| m.saturating_sub(1));
+    let name = match (last, first) {
+        (b'A', _) => "Up",
+        (b'B', _) => "Down",
+        (b'C', _) => "Right",
+        (b'D', _) => "Left",
+        (b'H', _) | (b'~', "1" | "7") => "Home",
+        (b'F', _) | (b'~', "4" | "8") => "End",
+        (b'Z', _) if !ss3 => return Some("S-Tab".to_string()),
+        (b'P', _) => "F1",
+        (b'Q', _) => "F2",
+        (b'R', _) => "F3",
+        (b'S', _) => "F4",
+        (b'~', "2") => "Insert",
+        (b'~', "3") => "Delete",
+        (b'~', "5") => "PgUp",
+        (b'~', "6") => "PgDn",
+        (b'~', "15") => "F5",
+        (b'~', "17") => "F6",
+        (b'~', "18") => "F7",
+        (b'~', "19") => "F8",
+        (b'~', "20") => "F9",
+        (b'~', "21") => "F10",
+        (b'~', "23") => "F11",
+        (b'~', "24") => "F12",
+        _ => return None,
+    };
+    let mut prefix = String::new();
+    if modifiers & 4 != 0 {
+        prefix.push_str("C-");
+    }
+    if modifiers & 2 != 0 {
+        prefix.push_str("M-");
+    }
+    if modifiers & 1 != 0 {
+        prefix.push_str("S-");
+    }
+    Some(prefix + name)
+}
+
+fn utf8_len(first: u8) -> usize {
+    match first.leading_ones() {
+        2 => 2,
+        3 => 3,
+        4 => 4,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn named(name: &str) -> super::Key {
+        super::Key::Named(name.to_string())
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(
+            super::parse(b"a\xc3\xa9\r\x03\x7f\t"),
+            vec![
+                super::Key::Char('a'),
+                super::Key::Char('\u{e9}'),
+                named("Enter"),
+                named("C-c"),
+                named("Backspace"),
+                named("Tab"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_escape_sequences() {
+        assert_eq!(
+            super::parse(b"\x1b[A\x1bOP\x1b[1;5C\x1b[5~\x1b[Z\x1bb\x1b"),
+            vec![
+                named("Up"),
+                named("F1"),
+                named("C-Right"),
+                named("PgUp"),
+                named("S-Tab"),
+                named("M-b"),
+                named("Esc"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_drops_unknown_and_incomplete_input() {
+        // an unknown sequence, an unterminated sequence, and a multibyte
+        // character which was cut off
+        assert_eq!(super::parse(b"\x1b[99~x"), vec![super::Key::Char('x')]);
+        assert_eq!(super::parse(b"x\x1b[1;5"), vec![super::Key::Char('x')]);
+        assert_eq!(super::parse(b"x\xe2\x82"), vec![super::Key::Char('x')]);
+    }
+
+    #[test]
+    fn encode_keys() {
+        assert_eq!(super::encode("a").unwrap(), b"a");
+        assert_eq!(super::encode("-").unwrap(), b"-");
+        assert_eq!(super::encode("Space").unwrap(), b" ");
+        assert_eq!(super::encode("C-c").unwrap(), b"\x03");
+        assert_eq!(super::encode("C-Space").unwrap(), b"\x00");
+        assert_eq!(super::encode("M-b").unwrap(), b"\x1bb");
+        assert_eq!(super::encode("S-a").unwrap(), b"A");
+        assert_eq!(super::encode("S-Tab").unwrap(), b"\x1b[Z");
+        assert_eq!(super::encode("Up").unwrap(), b"\x1b[A");
+        assert_eq!(super::encode("F1").unwrap(), b"\x1bOP");
+        assert_eq!(super::encode("F5").unwrap(), b"\x1b[15~");
+        assert_eq!(super::encode("C-S-Up").unwrap(), b"\x1b[1;6A");
+        assert_eq!(super::encode("Nope"), None);
+        assert_eq!(super::encode("C-1"), None);
+    }
+
+    #[test]
+    fn encode_round_trips_through_parse() {
+        for name in [
+            "Enter",
+            "Tab",
+            "S-Tab",
+            "Esc",
+            "Backspace",
+            "C-a",
+            "M-x",
+            "M-Enter",
+            "Up",
+            "C-Left",
+            "M-S-Down",
+            "Home",
+            "End",
+            "Insert",
+            "Delete",
+            "PgDn",
+            "F4",
+            "F12",
+            "S-F5",
+        ] {
+            let bytes = super::encode(name).unwrap();
+            assert_eq!(super::parse(&bytes), vec![named(name)], "{name}");
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Information about how a recording was made.
+//!
+//! ttyrec files only contain the recorded output, so anything else about
+//! the session is stored as JSON in a separate file next to the recording
+//! (see [`path`]). The file is written when recording starts, and written
+//! again with the exit status of the command once it exits (or without one,
+//! if the recording is stopped first).
+
+use std::os::unix::process::ExitStatusExt as _;
+
+/// Returns the path of the metadata file for the recording at `path`.
+pub fn path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(".meta");
+    path.into()
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    /// The command which was recorded (as passed to `ttyrec -c`, or the
+    /// shell which was run).
+    pub command: String,
+    /// The directory the command was run in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub term: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// When the recording started, in seconds since the unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<u64>,
+    /// The terminal size (as rows and columns) when the recording started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<(u16, u16)>,
+    /// How the command exited, or nothing if it was still running when the
+    /// recording stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<ExitStatus>,
+    /// When recording was paused, in order. Time spent paused isn't part of
+    /// the recording, so this is needed to tell when later parts of the
+    /// recording actually happened.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pauses: Vec<Pause>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Pause {
+    /// Seconds since the start of the recording.
+    pub time: f64,
+    /// How many seconds recording was paused for.
+    pub duration: f64,
+}
+
+impl Metadata {
+    /// Collects the metadata for a recording of `command` which is starting
+    /// now, in the current environment.
+    pub fn new(command: String, size: (u16, u16)) -> Self {
+        Self {
+            command,
+            cwd: std::env::current_dir()
+                .ok()
+                .map(|dir| dir.to_string_lossy().into_owned()),
+            term: std::env::var("TERM").ok(),
+            hostname: hostname(),
+            user: std::env::var("USER")
+                .or_else(|_| std::env::var("LOGNAME"))
+                .ok(),
+            start_time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .map(|time| time.as_secs()),
+            size: Some(size),
+            exit_status: None,
+            pauses: vec![],
+        }
+    }
+
+    /// Returns how long recording was paused for before `time` (relative to
+    /// the start of the recording).
+    pub fn paused_before(
+        &self,
+        time: std::time::Duration,
+    ) -> std::time::Duration {
+        let paused = self
+            .pauses
+            .iter()
+            .filter(|pause| pause.time <= time.as_secs_f64())
+            .map(|pause| pause.duration)
+            .sum();
+        std::time::Duration::try_from_secs_f64(paused).unwrap_or_default()
+    }
+
+    pub async fn write(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        tokio::fs::write(path, json).await?;
+        Ok(())
+    }
+
+    pub async fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        let json = tokio::fs::read(path).await?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExitStatus {
+    /// The command exited normally with this exit code.
+    Code(i32),
+    /// The command was killed by this signal.
+    Signal(i32),
+}
+
+impl From<std::process::ExitStatus> for ExitStatus {
+    fn from(status: std::process::ExitStatus) -> Self {
+        // a process which didn't exit normally must have been killed
+        status.code().map_or_else(
+            || Self::Signal(status.signal().unwrap_or(0)),
+            Self::Code,
+        )
+    }
+}
+
+impl ExitStatus {
+    /// Returns the exit code a shell would report for this status.
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Code(code) => code,
+            Self::Signal(signal) => 128 + signal,
+        }
+    }
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Code(code) => write!(f, "exited with status {code}"),
+            Self::Signal(signal) => write!(f, "killed by signal {signal}"),
+        }
+    }
+}
+
+/// Formats a time in seconds since the unix epoch as a UTC date and time.
+pub fn format_time(time: u64) -> String {
+    format!("{} UTC", format_utc(time))
+}
+
+// formats a time in seconds since the unix epoch as a UTC date and time,
+// like `2024-01-02 03:04:05`, leaving it up to callers to indicate the
+// timezone
+pub(crate) fn format_utc(time: u64) -> String {
+    let days = time / 86400;
+    let secs = time % 86400;
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn hostname() -> Option<String> {
+    let mut buf = [0_u8; 256];
+    // SAFETY: buf is valid for writes of buf.len() bytes
+    let res =
+        unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if res != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&c| c == 0)?;
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn paused_before() {
+        let metadata: super::Metadata = serde_json::from_str(
+            r#"{"command": "sh", "pauses": [
+                {"time": 5.0, "duration": 30.0},
+                {"time": 10.0, "duration": 0.5}
+            ]}"#,
+        )
+        .unwrap();
+        let paused = |secs| {
+            metadata.paused_before(std::time::Duration::from_secs(secs))
+        };
+        assert_eq!(paused(0), std::time::Duration::ZERO);
+        assert_eq!(paused(5), std::time::Duration::from_secs(30));
+        assert_eq!(paused(20), std::time::Duration::from_millis(30_500));
+
+        // metadata from before pauses were recorded
+        let metadata: super::Metadata =
+            serde_json::from_str(r#"{"command": "sh"}"#).unwrap();
+        assert!(metadata.pauses.is_empty());
+        assert_eq!(
+            metadata.paused_before(std::time::Duration::from_secs(20)),
+            std::time::Duration::ZERO
+        );
+    }
+}
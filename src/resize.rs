@@ -0,0 +1,121 @@
+//! Recording of terminal size changes.
+//!
+//! Size changes are stored inline in the recorded output, using the xterm
+//! window manipulation sequence `CSI 8 ; rows ; cols t`. Terminals which
+//! don't understand it will ignore it, and players which do can use it to
+//! parse each frame at the size it was originally recorded at.
+
+const PREFIX: &[u8] = b"\x1b[8;";
+
+/// Returns the escape sequence used to record a terminal size of `rows` by
+/// `cols`.
+pub fn sequence((rows, cols): (u16, u16)) -> Vec<u8> {
+    format!("\x1b[8;{rows};{cols}t").into_bytes()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunk<'a> {
+    Data(&'a [u8]),
+    Resize((u16, u16)),
+}
+
+/// Splits `data` into runs of ordinary output and the resize sequences
+/// between them, in order.
+pub fn chunks(data: &[u8]) -> impl Iterator<Item = Chunk<'_>> {
+    Chunks { data }
+}
+
+/// Feeds `data` to `parser`, resizing the parser's screen whenever a resize
+/// sequence is found.
+pub fn process<CB: vt100::Callbacks>(
+    parser: &mut vt100::Parser<CB>,
+    data: &[u8],
+) {
+    for chunk in chunks(data) {
+        match chunk {
+            Chunk::Data(data) => {
+                parser.process(data);
+            }
+            Chunk::Resize((rows, cols)) => {
+                parser.screen_mut().set_size(rows, cols);
+            }
+        }
+    }
+}
+
+struct Chunks<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Chunk<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        if let Some((size, len)) = parse_prefix(self.data) {
+            self.data = &self.data[len..];
+            return Some(Chunk::Resize(size));
+        }
+
+        let end = (1..self.data.len())
+            .find(|&i| {
+                self.data[i..].starts_with(PREFIX)
+                    && parse_prefix(&self.data[i..]).is_some()
+            })
+            .unwrap_or(self.data.len());
+        let (data, rest) = self.data.split_at(end);
+        self.data = rest;
+        Some(Chunk::Data(data))
+    }
+}
+
+fn parse_prefix(data: &[u8]) -> Option<((u16, u16), usize)> {
+    let rest = data.strip_prefix(PREFIX)?;
+    let (rows, rows_len) = parse_number(rest)?;
+    let rest = rest[rows_len..].strip_prefix(b";")?;
+    let (cols, cols_len) = parse_number(rest)?;
+    rest[cols_len..].strip_prefix(b"t")?;
+    if rows == 0 || cols == 0 {
+        return None;
+    }
+    Some(((rows, cols), PREFIX.len() + rows_len + 1 + cols_len + 1))
+}
+
+fn parse_number(data: &[u8]) -> Option<(u16, usize)> {
+    let len = data.iter().take_while(|c| c.is_ascii_digit()).count();
+    let n = std::str::from_utf8(&data[..len]).ok()?.parse().ok()?;
+    Some((n, len))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn chunks() {
+        let data = b"ab\x1b[8;30;100tcd\x1b[8;0;5t\x1b[8;1;x\x1b[8;2;3t";
+        assert_eq!(
+            super::chunks(data).collect::<Vec<_>>(),
+            [
+                super::Chunk::Data(b"ab"),
+                super::Chunk::Resize((30, 100)),
+                super::Chunk::Data(b"cd\x1b[8;0;5t\x1b[8;1;x"),
+                super::Chunk::Resize((2, 3)),
+            ]
+        );
+        assert_eq!(super::chunks(b"").count(), 0);
+        assert_eq!(
+            super::chunks(&super::sequence((24, 80))).collect::<Vec<_>>(),
+            [super::Chunk::Resize((24, 80))]
+        );
+    }
+
+    #[test]
+    fn process() {
+        let mut parser = vt100::Parser::default();
+        super::process(&mut parser, b"a\x1b[8;10;20tb");
+        assert_eq!(parser.screen().size(), (10, 20));
+        assert_eq!(parser.screen().contents(), "ab");
+    }
+}
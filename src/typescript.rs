@@ -0,0 +1,517 @@
+//! Reading and writing the typescript and timing files produced by
+//! `script` from util-linux.
+//!
+//! A recording in this format consists of a typescript file containing the
+//! raw output, and a timing file describing how the output was split up.
+//! The timing file is either in the classic format (lines of `DELAY BYTES`)
+//! or the advanced format (lines of `TYPE DELAY DATA`, which can also
+//! describe input, signals and information about the session).
+
+use tokio::io::AsyncBufReadExt as _;
+use tokio::io::AsyncReadExt as _;
+use tokio::io::AsyncWriteExt as _;
+
+const HEADER_PREFIX: &[u8] = b"Script started on ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum TimingFormat {
+    Classic,
+    Advanced,
+}
+
+/// Reads frames from a typescript and timing file pair, in the same form as
+/// ttyrec frames. Terminal size changes are returned as frames containing
+/// resize sequences (see [`crate::resize`]).
+pub struct Reader<L, T> {
+    log: tokio::io::BufReader<L>,
+    timing: tokio::io::BufReader<T>,
+    started: bool,
+    size: Option<(u16, u16)>,
+    logs: (Option<String>, Option<String>),
+    time: std::time::Duration,
+    pending: std::collections::VecDeque<ttyrec::Frame>,
+}
+
+impl<
+        L: tokio::io::AsyncRead + std::marker::Unpin + Send,
+        T: tokio::io::AsyncRead + std::marker::Unpin + Send,
+    > Reader<L, T>
+{
+    pub fn new(log: L, timing: T) -> Self {
+        Self {
+            log: tokio::io::BufReader::new(log),
+            timing: tokio::io::BufReader::new(timing),
+            started: false,
+            size: None,
+            logs: (None, None),
+            time: std::time::Duration::ZERO,
+            pending: std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Returns the next frame, or `None` at the end of the recording.
+    pub async fn read_frame(
+        &mut self,
+    ) -> anyhow::Result<Option<ttyrec::Frame>> {
+        if !self.started {
+            self.read_header().await?;
+        }
+        loop {
+            if let Some(frame) = self.pending.pop_front() {
+                return Ok(Some(frame));
+            }
+            let mut line = String::new();
+            if self.timing.read_line(&mut line).await? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            self.entry(line).await?;
+        }
+    }
+
+    // the typescript starts with a line of information about the session
+    // which isn't part of the output
+    async fn read_header(&mut self) -> anyhow::Result<()> {
+        self.started = true;
+        if !self.log.fill_buf().await?.starts_with(HEADER_PREFIX) {
+            return Ok(());
+        }
+        let mut header = vec![];
+        self.log.read_until(b'\n', &mut header).await?;
+        let header = String::from_utf8_lossy(&header);
+        if let (Some(cols), Some(rows)) = (
+            header_field(&header, "COLUMNS"),
+            header_field(&header, "LINES"),
+        ) {
+            self.size = Some((rows, cols));
+        }
+        Ok(())
+    }
+
+    async fn entry(&mut self, line: &str) -> anyhow::Result<()> {
+        let mut fields = line.splitn(3, ' ');
+        let first = fields.next().unwrap_or_default();
+        // classic timing files don't have a type field
+        if let Ok(delay) = first.parse::<f64>() {
+            let len = fields.next().unwrap_or_default();
+            return self.output(delay, len.parse()?).await;
+        }
+        let delay: f64 = fields.next().unwrap_or_default().parse()?;
+        let data = fields.next().unwrap_or_default();
+        match first {
+            "O" => self.output(delay, data.parse()?).await?,
+            "I" => {
+                self.advance(delay);
+                // if input was logged to the same file as the output, it
+                // still needs to be skipped over
+                if self.logs.0.is_some() && self.logs.0 == self.logs.1 {
+                    self.read_log(data.parse()?).await?;
+                }
+            }
+            "S" => {
+                self.advance(delay);
+                let mut rows = None;
+                let mut cols = None;
+                for field in data.split(' ') {
+                    if let Some(n) = field.strip_prefix("ROWS=") {
+                        rows = n.parse().ok();
+                    } else if let Some(n) = field.strip_prefix("COLS=") {
+                        cols = n.parse().ok();
+                    }
+                }
+                if let (Some(rows), Some(cols)) = (rows, cols) {
+                    self.start();
+                    self.pending.push_back(ttyrec::Frame {
+                        time: self.time,
+                        data: crate::resize::sequence((rows, cols)),
+                    });
+                }
+            }
+            "H" => {
+                let (name, value) =
+                    data.split_once(' ').unwrap_or((data, ""));
+                match name {
+                    "COLUMNS" => {
+                        let cols = value.parse()?;
+                        let rows = self.size.map_or(24, |size| size.0);
+                        self.size = Some((rows, cols));
+                    }
+                    "LINES" => {
+                        let rows = value.parse()?;
+                        let cols = self.size.map_or(80, |size| size.1);
+                        self.size = Some((rows, cols));
+                    }
+                    "OUTPUT_LOG" => self.logs.0 = Some(value.to_string()),
+                    "INPUT_LOG" => self.logs.1 = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            _ => anyhow::bail!("unknown timing file entry: {line}"),
+        }
+        Ok(())
+    }
+
+    async fn output(&mut self, delay: f64, len: usize) -> anyhow::Result<()> {
+        self.advance(delay);
+        self.start();
+        let data = self.read_log(len).await?;
+        self.pending.push_back(ttyrec::Frame {
+            time: self.time,
+            data,
+        });
+        Ok(())
+    }
+
+    // records the initial size before the first frame, once we have seen
+    // all of the information at the start of the timing file
+    fn start(&mut self) {
+        if let Some(size) = self.size.take() {
+            self.pending.push_back(ttyrec::Frame {
+                time: std::time::Duration::ZERO,
+                data: crate::resize::sequence(size),
+            });
+        }
+    }
+
+    fn advance(&mut self, delay: f64) {
+        self.time +=
+            std::time::Duration::try_from_secs_f64(delay).unwrap_or_default();
+    }
+
+    async fn read_log(&mut self, len: usize) -> anyhow::Result<Vec<u8>> {
+        let mut data = vec![];
+        (&mut self.log)
+            .take(u64::try_from(len)?)
+            .read_to_end(&mut data)
+            .await?;
+        if data.len() < len {
+            anyhow::bail!("typescript is shorter than the timing file");
+        }
+        Ok(data)
+    }
+}
+
+fn header_field(header: &str, name: &str) -> Option<u16> {
+    let start = header.find(&format!("{name}=\""))? + name.len() + 2;
+    let len = header[start..].find('"')?;
+    header[start..start + len].parse().ok()
+}
+
+/// Writes frames to a typescript and timing file pair.
+pub struct Writer<L, T> {
+    log: L,
+    timing: T,
+    format: TimingFormat,
+    size: (u16, u16),
+    start_time: Option<u64>,
+    time: std::time::Duration,
+    prev: std::time::Duration,
+}
+
+impl<
+        L: tokio::io::AsyncWrite + std::marker::Unpin + Send,
+        T: tokio::io::AsyncWrite + std::marker::Unpin + Send,
+    > Writer<L, T>
+{
+    pub async fn new(
+        mut log: L,
+        mut timing: T,
+        format: TimingFormat,
+        header: &crate::asciicast::Header,
+    ) -> anyhow::Result<Self> {
+        let started = header
+            .timestamp
+            .map_or_else(String::new, |time| format_time(time) + " ");
+        let mut fields = vec![];
+        if let Some(command) = &header.command {
+            fields.push(format!("COMMAND=\"{command}\""));
+        }
+        if let Some(term) =
+            header.env.as_ref().and_then(|env| env.get("TERM"))
+        {
+            fields.push(format!("TERM=\"{term}\""));
+        }
+        fields.push(format!("COLUMNS=\"{}\"", header.width));
+        fields.push(format!("LINES=\"{}\"", header.height));
+        log.write_all(
+            format!("Script started on {started}[{}]\n", fields.join(" "))
+                .as_bytes(),
+        )
+        .await?;
+
+        if format == TimingFormat::Advanced {
+            let mut entries = vec![];
+            if let Some(time) = header.timestamp {
+                entries.push(("START_TIME", format_time(time)));
+            }
+            if let Some(term) =
+                header.env.as_ref().and_then(|env| env.get("TERM"))
+            {
+                entries.push(("TERM", term.clone()));
+            }
+            entries.push(("COLUMNS", header.width.to_string()));
+            entries.push(("LINES", header.height.to_string()));
+            if let Some(command) = &header.command {
+                entries.push(("COMMAND", command.clone()));
+            }
+            for (name, value) in entries {
+                timing
+                    .write_all(
+                        format!("H 0.000000 {name} {value}\n").as_bytes(),
+                    )
+                    .await?;
+            }
+        }
+
+        Ok(Self {
+            log,
+            timing,
+            format,
+            size: header.size(),
+            start_time: header.timestamp,
+            time: std::time::Duration::ZERO,
+            prev: std::time::Duration::ZERO,
+        })
+    }
+
+    /// Writes a frame whose time is relative to the start of the recording.
+    pub async fn write_frame(
+        &mut self,
+        frame: &ttyrec::Frame,
+    ) -> anyhow::Result<()> {
+        self.time = frame.time;
+        for chunk in crate::resize::chunks(&frame.data) {
+            if let crate::resize::Chunk::Resize(size) = chunk {
+                if size == self.size {
+                    continue;
+                }
+                self.size = size;
+            }
+            let delay = self.time.saturating_sub(self.prev).as_secs_f64();
+            self.prev = self.time;
+            match (chunk, self.format) {
+                (crate::resize::Chunk::Data(data), TimingFormat::Classic) => {
+                    self.log.write_all(data).await?;
+                    self.timing
+                        .write_all(
+                            format!("{delay:.6} {}\n", data.len()).as_bytes(),
+                        )
+                        .await?;
+                }
+                (
+                    crate::resize::Chunk::Data(data),
+                    TimingFormat::Advanced,
+                ) => {
+                    self.log.write_all(data).await?;
+                    self.timing
+                        .write_all(
+                            format!("O {delay:.6} {}\n", data.len())
+                                .as_bytes(),
+                        )
+                        .await?;
+                }
+                // classic timing files have no way to record size changes,
+                // so leave them in the output, where we can still find them
+                (
+                    crate::resize::Chunk::Resize(size),
+                    TimingFormat::Classic,
+                ) => {
+                    let data = crate::resize::sequence(size);
+                    self.log.write_all(&data).await?;
+                    self.timing
+                        .write_all(
+                            format!("{delay:.6} {}\n", data.len()).as_bytes(),
+                        )
+                        .await?;
+                }
+                (
+                    crate::resize::Chunk::Resize((rows, cols)),
+                    TimingFormat::Advanced,
+                ) => {
+                    self.timing
+                        .write_all(
+                            format!(
+                                "S {delay:.6} SIGWINCH ROWS={rows} COLS={cols}\n"
+                            )
+                            .as_bytes(),
+                        )
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> anyhow::Result<()> {
+        let done = self.start_time.map_or_else(String::new, |time| {
+            format!(" on {}", format_time(time + self.time.as_secs()))
+        });
+        self.log
+            .write_all(format!("\nScript done{done}\n").as_bytes())
+            .await?;
+        if self.format == TimingFormat::Advanced {
+            self.timing
+                .write_all(
+                    format!(
+                        "H 0.000000 DURATION {:.6}\n",
+                        self.time.as_secs_f64()
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+        }
+        self.log.shutdown().await?;
+        self.timing.shutdown().await?;
+        Ok(())
+    }
+}
+
+// formats a unix timestamp the way script does, in UTC
+fn format_time(time: u64) -> String {
+    format!("{}+00:00", crate::metadata::format_utc(time))
+}
+
+#[cfg(test)]
+mod tests {
+    fn frame(millis: u64, data: &[u8]) -> ttyrec::Frame {
+        ttyrec::Frame {
+            time: std::time::Duration::from_millis(millis),
+            data: data.to_vec(),
+        }
+    }
+
+    async fn read(log: &[u8], timing: &[u8]) -> Vec<ttyrec::Frame> {
+        let mut reader = super::Reader::new(log, timing);
+        let mut frames = vec![];
+        while let Some(frame) = reader.read_frame().await.unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn read_classic() {
+        let log = b"Script started on 2024-01-02 03:04:05+00:00 \
+            [TERM=\"xterm\" COLUMNS=\"100\" LINES=\"30\"]\nhello world";
+        let timing = b"0.500000 5\n1.250000 6\n";
+        assert_eq!(
+            read(log, timing).await,
+            vec![
+                frame(0, &crate::resize::sequence((30, 100))),
+                frame(500, b"hello"),
+                frame(1750, b" world"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn read_advanced() {
+        let log = b"Script started on 2024-01-02 03:04:05+00:00 \
+            [COLUMNS=\"100\" LINES=\"30\"]\nhello world";
+        let timing = b"H 0.000000 COLUMNS 100\n\
+            H 0.000000 LINES 30\n\
+            O 0.500000 5\n\
+            I 0.250000 1\n\
+            S 0.250000 SIGWINCH ROWS=40 COLS=120\n\
+            O 0.500000 6\n\
+            H 0.000000 DURATION 1.500000\n";
+        assert_eq!(
+            read(log, timing).await,
+            vec![
+                frame(0, &crate::resize::sequence((30, 100))),
+                frame(500, b"hello"),
+                frame(1000, &crate::resize::sequence((40, 120))),
+                frame(1500, b" world"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn read_input_in_the_same_log() {
+        // with `script --log-io`, input is in the typescript too
+        let log = b"$ lsls\r\n";
+        let timing = b"H 0.000000 OUTPUT_LOG typescript\n\
+            H 0.000000 INPUT_LOG typescript\n\
+            O 0.100000 2\n\
+            I 0.100000 2\n\
+            O 0.100000 4\n";
+        assert_eq!(
+            read(log, timing).await,
+            vec![frame(100, b"$ "), frame(300, b"ls\r\n")]
+        );
+    }
+
+    #[tokio::test]
+    async fn read_errors() {
+        let mut reader = super::Reader::new(&b"abc"[..], &b"0.1 5\n"[..]);
+        assert!(reader.read_frame().await.is_err());
+        let mut reader = super::Reader::new(&b"abc"[..], &b"X 0.1 3\n"[..]);
+        assert!(reader.read_frame().await.is_err());
+    }
+
+    async fn round_trip(format: super::TimingFormat) -> (String, String) {
+        let frames = vec![
+            frame(0, &crate::resize::sequence((24, 80))),
+            frame(250, b"hello"),
+            frame(1000, &crate::resize::sequence((40, 120))),
+            frame(1500, b" world"),
+        ];
+        let mut header = crate::asciicast::Header::new((24, 80));
+        header.timestamp = Some(1_704_164_645);
+        let mut log = vec![];
+        let mut timing = vec![];
+        let mut writer =
+            super::Writer::new(&mut log, &mut timing, format, &header)
+                .await
+                .unwrap();
+        for frame in &frames {
+            writer.write_frame(frame).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        let read_frames = read(&log, &timing).await;
+        assert_eq!(read_frames, frames, "{format:?}");
+        (
+            String::from_utf8(log).unwrap(),
+            String::from_utf8(timing).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn write_classic() {
+        let (log, timing) = round_trip(super::TimingFormat::Classic).await;
+        assert!(log.starts_with(
+            "Script started on 2024-01-02 03:04:05+00:00 \
+                [COLUMNS=\"80\" LINES=\"24\"]\n"
+        ));
+        assert!(log.ends_with("\nScript done on 2024-01-02 03:04:06+00:00\n"));
+        // the resize has nowhere else to go, so it is left in the output
+        assert_eq!(
+            timing,
+            format!(
+                "0.250000 5\n0.750000 {}\n0.500000 6\n",
+                crate::resize::sequence((40, 120)).len()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn write_advanced() {
+        let (log, timing) = round_trip(super::TimingFormat::Advanced).await;
+        assert!(log.contains("\nhello world\nScript done on "));
+        assert_eq!(
+            timing,
+            "H 0.000000 START_TIME 2024-01-02 03:04:05+00:00\n\
+                H 0.000000 COLUMNS 80\n\
+                H 0.000000 LINES 24\n\
+                O 0.250000 5\n\
+                S 0.750000 SIGWINCH ROWS=40 COLS=120\n\
+                O 0.500000 6\n\
+                H 0.000000 DURATION 1.500000\n"
+        );
+    }
+}